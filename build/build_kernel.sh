@@ -3,15 +3,23 @@
 nasm -f elf32 src/boot/multiboot_header.asm
 nasm -f elf32 src/boot/boot.asm
 nasm -f elf32 src/boot/utils.asm
+nasm -f elf32 src/boot/interrupts.asm
+nasm -f elf32 src/boot/user_programs.asm
 RUST_TARGET_PATH=$(pwd) xargo build --target=i386-unknown-none
 
 ld -m elf_i386 -n -o kfs.bin -T linker.ld \
     src/boot/multiboot_header.o \
     src/boot/boot.o \
     src/boot/utils.o \
+    src/boot/interrupts.o \
+    src/boot/user_programs.o \
     target/i386-unknown-none/debug/libkfs.a
 
 mv kfs.bin ./isofiles/boot/kernel.bin
 
+# User programs, loaded by GRUB as modules
+nasm -f elf32 src/user/hello.asm
+ld -m elf_i386 -e _start -Ttext 0x08048000 -o ./isofiles/boot/hello src/user/hello.o
+
 # Create ISO image using grub-mkrescue
 grub-mkrescue -o kfs.iso ./isofiles
\ No newline at end of file
@@ -0,0 +1,30 @@
+#!/bin/bash
+# Builds the kernel with its #[test_case] functions into kfs-test.iso, `make test` runs it
+
+set -e
+
+nasm -f elf32 src/boot/multiboot_header.asm
+nasm -f elf32 src/boot/boot.asm
+nasm -f elf32 src/boot/utils.asm
+nasm -f elf32 src/boot/interrupts.asm
+nasm -f elf32 src/boot/user_programs.asm
+
+# rustc builds the test harness as an executable, so it links it itself, the
+# same way build_kernel.sh does with ld
+LINK_ARGS="-C link-arg=-T$(pwd)/linker.ld"
+for object in multiboot_header boot utils interrupts user_programs; do
+    LINK_ARGS="$LINK_ARGS -C link-arg=$(pwd)/src/boot/$object.o"
+done
+RUSTFLAGS="-Z panic_abort_tests $LINK_ARGS" RUST_TARGET_PATH=$(pwd) xargo test --no-run --target=i386-unknown-none
+
+TEST_KERNEL=$(ls -t target/i386-unknown-none/debug/deps/kfs-* | grep -v '\.d$' | head -n 1)
+
+nasm -f elf32 src/user/hello.asm
+ld -m elf_i386 -e _start -Ttext 0x08048000 -o src/user/hello src/user/hello.o
+
+# same GRUB setup as the real image
+rm -rf target/isofiles-test
+cp -r isofiles target/isofiles-test
+cp "$TEST_KERNEL" target/isofiles-test/boot/kernel.bin
+mv src/user/hello target/isofiles-test/boot/hello
+grub-mkrescue -o kfs-test.iso target/isofiles-test
@@ -0,0 +1,103 @@
+// Walks the saved frame pointers of a 32-bit stack. Each frame starts with
+// the caller's ebp, and above it the address the call returns to:
+//
+//     ebp + 4   return address
+//     ebp       caller's ebp
+//
+// An ebp of 0 ends the chain, it is what the boot code and new threads
+// start with.
+
+pub const MAX_FRAMES: usize = 32;
+
+pub struct Frames<F: Fn(usize) -> u32> {
+    ebp: usize,
+    // where frames can be, anything else means the chain is broken
+    low: usize,
+    high: usize,
+    read: F,
+    depth: usize,
+}
+
+impl<F: Fn(usize) -> u32> Frames<F> {
+    pub fn new(ebp: usize, low: usize, high: usize, read: F) -> Frames<F> {
+        Frames { ebp, low, high, read, depth: 0 }
+    }
+}
+
+impl<F: Fn(usize) -> u32> Iterator for Frames<F> {
+    // the return address
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let ebp = self.ebp;
+        if ebp == 0 || ebp & 3 != 0 || ebp < self.low || ebp > self.high - 8 || self.depth == MAX_FRAMES {
+            return None;
+        }
+        let caller = (self.read)(ebp) as usize;
+        let eip = (self.read)(ebp + 4) as usize;
+        if eip == 0 {
+            return None;
+        }
+        self.depth += 1;
+        // the stack grows down, callers are further up. Anything else and
+        // we would go around in circles.
+        self.ebp = if caller > ebp { caller } else { 0 };
+        Some(eip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: usize = 0x1000;
+
+    // a stack at BASE, one u32 per index
+    fn frames<'a>(stack: &'a [u32], ebp: usize) -> Frames<impl Fn(usize) -> u32 + 'a> {
+        Frames::new(ebp, BASE, BASE + stack.len() * 4, move |address| stack[(address - BASE) / 4])
+    }
+
+    #[test]
+    fn chain_ends_at_a_null_frame() {
+        let mut stack = [0u32; 16];
+        // innermost frame at index 2, its caller at 6, then the outermost at 12
+        stack[2] = (BASE + 6 * 4) as u32;
+        stack[3] = 0xC010_0010;
+        stack[6] = (BASE + 12 * 4) as u32;
+        stack[7] = 0xC010_0020;
+        stack[12] = 0;
+        stack[13] = 0xC010_0030;
+        let addresses: Vec<usize> = frames(&stack, BASE + 2 * 4).collect();
+        assert_eq!(addresses, [0xC010_0010, 0xC010_0020, 0xC010_0030]);
+    }
+
+    #[test]
+    fn frames_going_down_stop_the_walk() {
+        let mut stack = [0u32; 8];
+        // a caller below its callee, or itself, is garbage
+        stack[4] = (BASE + 4 * 4) as u32;
+        stack[5] = 0xC010_0010;
+        let addresses: Vec<usize> = frames(&stack, BASE + 4 * 4).collect();
+        assert_eq!(addresses, [0xC010_0010]);
+    }
+
+    #[test]
+    fn frames_outside_the_stack_are_not_read() {
+        let stack = [0u32; 8];
+        assert_eq!(frames(&stack, 0).count(), 0);
+        assert_eq!(frames(&stack, BASE - 4).count(), 0);
+        assert_eq!(frames(&stack, BASE + 7 * 4).count(), 0);
+        assert_eq!(frames(&stack, BASE + 2).count(), 0);
+    }
+
+    #[test]
+    fn walk_is_bounded() {
+        // every frame calls from right above it, more than we print
+        let mut stack = [0u32; 2 * (MAX_FRAMES + 4)];
+        for frame in 0..MAX_FRAMES + 3 {
+            stack[frame * 2] = (BASE + (frame + 1) * 8) as u32;
+            stack[frame * 2 + 1] = 0xC010_0000 + frame as u32;
+        }
+        assert_eq!(frames(&stack, BASE).count(), MAX_FRAMES);
+    }
+}
@@ -0,0 +1,52 @@
+use hal::PortIo;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+// real time clock registers
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+
+pub fn bcd_to_binary(bcd: u8) -> u8 {
+    ((bcd & 0xf0) >> 4) * 10 + (bcd & 0x0f)
+}
+
+pub fn read_cmos<P: PortIo>(ports: &mut P, register: u8) -> u8 {
+    ports.write_u8(CMOS_ADDRESS, register);
+    ports.read_u8(CMOS_DATA)
+}
+
+// hours, minutes and seconds, the clock counts in BCD
+pub fn rtc_time<P: PortIo>(ports: &mut P) -> (u8, u8, u8) {
+    let seconds = bcd_to_binary(read_cmos(ports, SECONDS));
+    let minutes = bcd_to_binary(read_cmos(ports, MINUTES));
+    let hours = bcd_to_binary(read_cmos(ports, HOURS));
+    (hours, minutes, seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockPorts;
+
+    #[test]
+    fn bcd_digits_are_tens_and_units() {
+        assert_eq!(bcd_to_binary(0x00), 0);
+        assert_eq!(bcd_to_binary(0x09), 9);
+        assert_eq!(bcd_to_binary(0x42), 42);
+        assert_eq!(bcd_to_binary(0x59), 59);
+    }
+
+    #[test]
+    fn time_comes_from_the_clock_registers() {
+        let mut ports = MockPorts::new();
+        ports.indexes.push(CMOS_ADDRESS);
+        ports.registers.insert(SECONDS, 0x37);
+        ports.registers.insert(MINUTES, 0x05);
+        ports.registers.insert(HOURS, 0x23);
+        assert_eq!(rtc_time(&mut ports), (23, 5, 37));
+        // each register is selected before it is read
+        assert_eq!(ports.writes, [(CMOS_ADDRESS, SECONDS), (CMOS_ADDRESS, MINUTES), (CMOS_ADDRESS, HOURS)]);
+    }
+}
@@ -0,0 +1,63 @@
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct GdtEntry {
+    pub limit_low: u16,
+    pub base_low: u16,
+    pub base_middle: u8,
+    pub access: u8,
+    pub granularity: u8,
+    pub base_high: u8,
+}
+
+impl GdtEntry {
+    pub fn new(base: u32 , limit: u32, access: u8, other:u8) -> GdtEntry {
+        GdtEntry {
+            limit_low: (limit & 0xffff) as u16,
+            base_low: (base & 0xffff) as u16,
+            base_middle: ((base >> 16) & 0xff) as u8,
+            access,
+            granularity: (other & 0xf0) | (((limit >> 16) & 0x0f) as u8),
+            base_high: ((base >> 24) & 0xff) as u8,
+        }
+    }
+
+    pub fn base(&self) -> u32 {
+        (self.base_high as u32) << 24 | (self.base_middle as u32) << 16 | self.base_low as u32
+    }
+
+    pub fn limit(&self) -> u32 {
+        ((self.granularity & 0x0f) as u32) << 16 | self.limit_low as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_is_eight_bytes() {
+        assert_eq!(core::mem::size_of::<GdtEntry>(), 8);
+    }
+
+    #[test]
+    fn entry_splits_base_and_limit() {
+        let entry = GdtEntry::new(0x12345678, 0xABCDE, 0x9A, 0xCF);
+        // copies, the fields of a packed struct can't be borrowed
+        assert_eq!({ entry.limit_low }, 0xBCDE);
+        assert_eq!({ entry.base_low }, 0x5678);
+        assert_eq!({ entry.base_middle }, 0x34);
+        assert_eq!({ entry.base_high }, 0x12);
+        assert_eq!({ entry.access }, 0x9A);
+        // flags in the high nibble, the top of the limit in the low one
+        assert_eq!({ entry.granularity }, 0xCA);
+    }
+
+    #[test]
+    fn entry_reads_back_base_and_limit() {
+        let entry = GdtEntry::new(0xC0000000, 0xFFFFF, 0x92, 0xCF);
+        assert_eq!(entry.base(), 0xC0000000);
+        assert_eq!(entry.limit(), 0xFFFFF);
+        // the low nibble of the flags is the limit's, it doesn't leak in
+        assert_eq!(GdtEntry::new(0, 0x12345, 0x92, 0xFF).limit(), 0x12345);
+    }
+}
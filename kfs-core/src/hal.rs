@@ -0,0 +1,17 @@
+use screen::ScreenChar;
+
+pub trait PortIo {
+    fn read_u8(&mut self, port: u16) -> u8;
+    fn write_u8(&mut self, port: u16, value: u8);
+}
+
+// a grid of BUFFER_HEIGHT rows of BUFFER_WIDTH characters, like the VGA text mode
+pub trait TextScreen {
+    fn read(&self, row: usize, col: usize) -> ScreenChar;
+    fn write(&mut self, row: usize, col: usize, char: ScreenChar);
+}
+
+pub trait Clock {
+    // hours, minutes and seconds
+    fn time_of_day(&self) -> (u8, u8, u8);
+}
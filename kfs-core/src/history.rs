@@ -0,0 +1,99 @@
+use screen::{Color, ColorCode, ScreenChar, BUFFER_WIDTH};
+
+pub const LINE_NB: usize = 200;
+
+// the lines that scrolled off a tab, the oldest ones are forgotten
+pub struct Vec {
+    pub buffer: [[ScreenChar; BUFFER_WIDTH]; LINE_NB],
+    pub oldest: usize,
+    pub newest: usize,
+    pub size: usize,
+}
+
+impl Vec {
+    pub fn new() -> Self {
+        Vec {
+            buffer: [[ScreenChar {
+                ascii: b' ',
+                color: ColorCode::new(Color::White, Color::Black),
+            }; BUFFER_WIDTH]; LINE_NB],
+            oldest: 0,
+            newest: 0,
+            size: 0,
+        }
+    }
+
+    pub fn push_new_line(&mut self, line: [ScreenChar; BUFFER_WIDTH]) {
+        if self.size == self.buffer.len() {
+            self.pop_oldest_line();
+        }
+        self.buffer[self.newest] = line;
+        self.newest = (self.newest + 1) % self.buffer.len();
+        self.size += 1
+    }
+
+    pub fn pop_oldest_line(&mut self) -> Option<[ScreenChar; BUFFER_WIDTH]> {
+        if self.size == 0 {
+            None
+        } else {
+            let line = self.buffer[self.oldest];
+            self.oldest = (self.oldest + 1) % self.buffer.len();
+            self.size -= 1;
+            Some(line)
+        }
+    }
+
+    // the line shown on the given row of a view of `rows` lines, scrolled
+    // back by `scroll` lines
+    pub fn visible_line(&self, rows: usize, row: usize, scroll: usize) -> &[ScreenChar; BUFFER_WIDTH] {
+        &self.buffer[(LINE_NB - rows + row + self.newest - scroll) % LINE_NB]
+    }
+}
+
+impl Default for Vec {
+    fn default() -> Self {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(ascii: u8) -> [ScreenChar; BUFFER_WIDTH] {
+        [ScreenChar { ascii, color: ColorCode::new(Color::White, Color::Black) }; BUFFER_WIDTH]
+    }
+
+    #[test]
+    fn history_pops_in_order() {
+        let mut lines = Vec::new();
+        assert!(lines.pop_oldest_line().is_none());
+        lines.push_new_line(line(b'a'));
+        lines.push_new_line(line(b'b'));
+        assert_eq!(lines.size, 2);
+        assert_eq!(lines.pop_oldest_line().map(|line| line[0].ascii), Some(b'a'));
+        assert_eq!(lines.pop_oldest_line().map(|line| line[0].ascii), Some(b'b'));
+        assert!(lines.pop_oldest_line().is_none());
+    }
+
+    #[test]
+    fn history_forgets_the_oldest_lines() {
+        let mut lines = Vec::new();
+        for i in 0..LINE_NB + 5 {
+            lines.push_new_line(line(i as u8));
+        }
+        assert_eq!(lines.size, LINE_NB);
+        assert_eq!(lines.pop_oldest_line().map(|line| line[0].ascii), Some(5));
+    }
+
+    #[test]
+    fn last_row_shows_the_newest_line() {
+        let mut lines = Vec::new();
+        for i in 0..30 {
+            lines.push_new_line(line(i as u8));
+        }
+        assert_eq!(lines.visible_line(23, 22, 0)[0].ascii, 29);
+        assert_eq!(lines.visible_line(23, 0, 0)[0].ascii, 7);
+        assert_eq!(lines.visible_line(23, 22, 5)[0].ascii, 24);
+    }
+}
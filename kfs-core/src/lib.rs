@@ -0,0 +1,21 @@
+// What the kernel does that doesn't need the hardware to be there. The
+// hardware itself is behind the traits of hal, the kernel implements them
+// for real and mock implements them for the tests, which run on the host
+// with a plain `cargo test`.
+#![cfg_attr(not(test), no_std)]
+
+// the tests use std, the code still says core
+#[cfg(test)]
+extern crate core;
+
+pub mod backtrace;
+pub mod cmos;
+pub mod gdt;
+pub mod hal;
+pub mod history;
+pub mod parse;
+pub mod screen;
+pub mod tetris;
+
+#[cfg(test)]
+mod mock;
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use hal::{Clock, PortIo, TextScreen};
+use screen::{Color, ColorCode, ScreenChar, BUFFER_HEIGHT, BUFFER_WIDTH};
+
+// Every write is recorded. A port in `indexes` selects a register of the
+// data port right after it, like the CMOS does, anything else reads as a
+// floating bus.
+pub struct MockPorts {
+    pub registers: HashMap<u8, u8>,
+    pub indexes: Vec<u16>,
+    pub writes: Vec<(u16, u8)>,
+    selected: Option<u8>,
+}
+
+impl MockPorts {
+    pub fn new() -> MockPorts {
+        MockPorts { registers: HashMap::new(), indexes: Vec::new(), writes: Vec::new(), selected: None }
+    }
+}
+
+impl PortIo for MockPorts {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        if self.indexes.iter().any(|&index| index + 1 == port) {
+            if let Some(register) = self.selected {
+                return *self.registers.get(&register).unwrap_or(&0);
+            }
+        }
+        0xFF
+    }
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        if self.indexes.contains(&port) {
+            self.selected = Some(value);
+        }
+        self.writes.push((port, value));
+    }
+}
+
+pub struct MockScreen {
+    pub chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl MockScreen {
+    pub fn new() -> MockScreen {
+        let blank = ScreenChar { ascii: b' ', color: ColorCode::new(Color::White, Color::Black) };
+        MockScreen { chars: [[blank; BUFFER_WIDTH]; BUFFER_HEIGHT] }
+    }
+
+    pub fn row_text(&self, row: usize) -> String {
+        self.chars[row].iter().map(|char| char.ascii as char).collect()
+    }
+}
+
+impl TextScreen for MockScreen {
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col]
+    }
+
+    fn write(&mut self, row: usize, col: usize, char: ScreenChar) {
+        self.chars[row][col] = char;
+    }
+}
+
+pub struct MockClock {
+    pub time: (u8, u8, u8),
+}
+
+impl Clock for MockClock {
+    fn time_of_day(&self) -> (u8, u8, u8) {
+        self.time
+    }
+}
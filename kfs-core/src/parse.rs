@@ -0,0 +1,53 @@
+// "10.9" is 109, "10" is 100
+pub fn atotenths(s: &str) -> Option<usize> {
+    let (units, tenths) = match s.split_once('.') {
+        Some((units, tenths)) if tenths.len() == 1 => (units, tenths),
+        Some(_) => return None,
+        None => (s, "0"),
+    };
+    atousize(units)?.checked_mul(10)?.checked_add(atousize(tenths)?)
+}
+
+pub fn atousize(s: &str) -> Option<usize> {
+    let mut result: usize = 0;
+    let chars = s.chars().peekable();
+
+    for c in chars {
+        if let Some(digit) = c.to_digit(10) {
+            result = result.checked_mul(10)?.checked_add(digit as usize)?;
+        } else {
+            return None;
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atousize_parses_digits() {
+        assert_eq!(atousize("0"), Some(0));
+        assert_eq!(atousize("42"), Some(42));
+        assert_eq!(atousize("4294967295"), Some(4294967295));
+        assert_eq!(atousize(&usize::MAX.to_string()), Some(usize::MAX));
+    }
+
+    #[test]
+    fn atousize_rejects_the_rest() {
+        assert_eq!(atousize("-1"), None);
+        assert_eq!(atousize("12a"), None);
+        assert_eq!(atousize(" 1"), None);
+        // one more than usize holds, whatever its size on this machine
+        assert_eq!(atousize(&(usize::MAX as u128 + 1).to_string()), None);
+    }
+
+    #[test]
+    fn atotenths_takes_one_decimal() {
+        assert_eq!(atotenths("10.9"), Some(109));
+        assert_eq!(atotenths("30"), Some(300));
+        assert_eq!(atotenths("2.50"), None);
+        assert_eq!(atotenths("2."), None);
+    }
+}
@@ -0,0 +1,119 @@
+use hal::TextScreen;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black      = 0,
+    Blue       = 1,
+    Green      = 2,
+    Cyan       = 3,
+    Red        = 4,
+    Magenta    = 5,
+    Brown      = 6,
+    LightGray  = 7,
+    DarkGray   = 8,
+    LightBlue  = 9,
+    LightGreen = 10,
+    LightCyan  = 11,
+    LightRed   = 12,
+    Pink       = 13,
+    Yellow     = 14,
+    White      = 15,
+}
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(pub u8);
+
+impl ColorCode {
+    pub fn new(foreground: Color, background: Color) -> ColorCode {
+        ColorCode((background as u8) << 4 | (foreground as u8))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)] // to guarantee field order is kept
+pub struct ScreenChar {
+    pub ascii: u8,
+    pub color: ColorCode,
+}
+
+pub fn draw_char<S: TextScreen>(screen: &mut S, row: usize, col: usize, char: u8, foreground: Color, background: Color) {
+    screen.write(row, col, ScreenChar { ascii: char, color: ColorCode::new(foreground, background) });
+}
+
+pub fn draw_str<S: TextScreen>(screen: &mut S, row: usize, col: usize, s: &str, foreground: Color, background: Color) {
+    for (index, c) in s.chars().enumerate() {
+        draw_char(screen, row, col + index, c as u8, foreground, background);
+    }
+}
+
+// right aligned, the last digit goes at col
+pub fn draw_nbr<S: TextScreen>(screen: &mut S, row: usize, col: usize, n: u32, foreground: Color, background: Color) {
+    if n >= 10 {
+        draw_nbr(screen, row, col - 1, n / 10, foreground, background);
+    }
+    draw_char(screen, row, col, b'0' + (n % 10) as u8, foreground, background);
+}
+
+// a single line box in code page 437, corners included
+pub fn draw_rectangle<S: TextScreen>(screen: &mut S, row_up: usize, row_down: usize, col_left: usize, col_right: usize) {
+    for row in (row_up + 1)..row_down {
+        draw_char(screen, row, col_left, 0xB3, Color::White, Color::Black);
+        draw_char(screen, row, col_right, 0xB3, Color::White, Color::Black);
+    }
+    for col in (col_left + 1)..col_right {
+        draw_char(screen, row_up, col, 0xC4, Color::White, Color::Black);
+        draw_char(screen, row_down, col, 0xC4, Color::White, Color::Black);
+    }
+    draw_char(screen, row_up, col_left, 0xDA, Color::White, Color::Black);
+    draw_char(screen, row_up, col_right, 0xBF, Color::White, Color::Black);
+    draw_char(screen, row_down, col_left, 0xC0, Color::White, Color::Black);
+    draw_char(screen, row_down, col_right, 0xD9, Color::White, Color::Black);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockScreen;
+
+    #[test]
+    fn color_code_packs_background_high() {
+        assert_eq!(ColorCode::new(Color::Yellow, Color::Blue), ColorCode(0x1E));
+        assert_eq!(ColorCode::new(Color::White, Color::Black), ColorCode(0x0F));
+    }
+
+    #[test]
+    fn numbers_are_right_aligned() {
+        let mut screen = MockScreen::new();
+        draw_nbr(&mut screen, 3, 24, 1230, Color::White, Color::Black);
+        assert_eq!(&screen.row_text(3)[21..25], "1230");
+        draw_nbr(&mut screen, 4, 24, 0, Color::White, Color::Black);
+        assert_eq!(&screen.row_text(4)[23..26], " 0 ");
+    }
+
+    #[test]
+    fn strings_keep_their_colors() {
+        let mut screen = MockScreen::new();
+        draw_str(&mut screen, 9, 36, "GAME OVER", Color::Red, Color::Black);
+        assert_eq!(&screen.row_text(9)[36..45], "GAME OVER");
+        assert_eq!(screen.read(9, 36).color, ColorCode::new(Color::Red, Color::Black));
+    }
+
+    #[test]
+    fn rectangle_has_corners_and_sides() {
+        let mut screen = MockScreen::new();
+        draw_rectangle(&mut screen, 2, 6, 9, 26);
+        assert_eq!(screen.read(2, 9).ascii, 0xDA);
+        assert_eq!(screen.read(2, 26).ascii, 0xBF);
+        assert_eq!(screen.read(6, 9).ascii, 0xC0);
+        assert_eq!(screen.read(6, 26).ascii, 0xD9);
+        assert_eq!(screen.read(4, 9).ascii, 0xB3);
+        assert_eq!(screen.read(6, 15).ascii, 0xC4);
+        // the inside is left alone
+        assert_eq!(screen.read(4, 15).ascii, b' ');
+    }
+}
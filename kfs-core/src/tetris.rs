@@ -0,0 +1,402 @@
+use core::cell::Cell;
+
+use hal::Clock;
+
+// columns of rows, the bottom row first. The two rows above the top of the
+// well are where new pieces appear.
+pub type Board = [[u8; 22]; 10];
+
+pub const WIDTH: usize = 10;
+pub const HEIGHT: usize = 22;
+
+pub struct SimpleRng {
+    state: Cell<u32>,
+}
+
+impl SimpleRng {
+    pub fn new(seed: u32) -> Self {
+        SimpleRng {
+            state: Cell::new(seed),
+        }
+    }
+
+    pub fn next_u32(&self) -> u32 {
+        let a: u32 = 1664525;
+        let c: u32 = 1013904223;
+
+        let state = self.state.get();
+        let next_state = state.wrapping_mul(a).wrapping_add(c);
+        self.state.set(next_state);
+
+        next_state
+    }
+}
+
+// a different game every second of the hour
+pub fn seed<C: Clock>(clock: &C) -> u32 {
+    let (_, minutes, seconds) = clock.time_of_day();
+    minutes as u32 * 100 + seconds as u32
+}
+
+pub fn name_to_index(c: char) -> usize {
+    match c {
+        'I' => 0,
+        'J' => 1,
+        'L' => 2,
+        'O' => 3,
+        'S' => 4,
+        'Z' => 5,
+        'T' => 6,
+        _ => 7,
+    }
+}
+
+// whether the piece, with its 4x4 grid's top left corner at (x, y + 4),
+// stays inside the board without covering anything
+pub fn fits(board: &Board, piece: usize, rot: usize, x: i32, y: i32) -> bool {
+    for (row, line) in ROT_ARRAY[piece][rot].iter().enumerate() {
+        for (col, &cell) in line.iter().enumerate() {
+            if cell == 0 {
+                continue;
+            }
+            let (cell_x, cell_y) = (x + col as i32, y + 4 - row as i32);
+            if cell_x < 0 || cell_x >= WIDTH as i32 || cell_y < 0 || cell_y >= HEIGHT as i32 ||
+                board[cell_x as usize][cell_y as usize] != 0 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn clear_line(board: &mut Board, y_stop: usize) {
+    for y in y_stop..20 {
+        for column in board.iter_mut() {
+            column[y] = column[y + 1];
+        }
+    }
+}
+
+// the number of full lines taken out, what was above them falls down
+pub fn clear_lines(board: &mut Board) -> u32 {
+    let mut n_line_cleared = 0;
+    let mut y: i32 = 0;
+    while y < HEIGHT as i32 {
+        for x in 0..WIDTH {
+            if board[x][y as usize] == 0 {
+                break;
+            }
+            if x == WIDTH - 1 {
+                clear_line(board, y as usize);
+                n_line_cleared += 1;
+                y -= 1;
+            }
+        }
+        y += 1;
+    }
+    n_line_cleared
+}
+
+// more lines at once are worth more
+pub fn line_score(lines: u32, level: u32) -> u32 {
+    match lines {
+        1 => 100 * level,
+        2 => 300 * level,
+        3 => 500 * level,
+        4 => 800 * level,
+        _ => 0,
+    }
+}
+
+pub fn fall_period_ms(level: u32) -> u32 {
+    if level >= 15 {
+        1000 / 30
+    }
+    else {
+        (16 - level) * 1000 / 30
+    }
+}
+
+pub const ROT_ARRAY: [[[[u8; 4]; 4]; 4]; 7] = [
+        [ // I
+            [
+                [0, 0, 0, 0],
+                [1, 1, 1, 1],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 1, 0],
+                [0, 0, 1, 0],
+                [0, 0, 1, 0],
+                [0, 0, 1, 0]
+            ],
+            [
+                [0, 0, 0, 0],
+                [0, 0, 0, 0],
+                [1, 1, 1, 1],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 1, 0, 0],
+                [0, 1, 0, 0],
+                [0, 1, 0, 0],
+                [0, 1, 0, 0]
+            ]
+        ],
+        [ // J
+            [
+                [2, 0, 0, 0],
+                [2, 2, 2, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 2, 2, 0],
+                [0, 2, 0, 0],
+                [0, 2, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 0, 0],
+                [2, 2, 2, 0],
+                [0, 0, 2, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 2, 0, 0],
+                [0, 2, 0, 0],
+                [2, 2, 0, 0],
+                [0, 0, 0, 0]
+            ]
+        ],
+        [ // L
+            [
+                [0, 0, 3, 0],
+                [3, 3, 3, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 3, 0, 0],
+                [0, 3, 0, 0],
+                [0, 3, 3, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 0, 0],
+                [3, 3, 3, 0],
+                [3, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [3, 3, 0, 0],
+                [0, 3, 0, 0],
+                [0, 3, 0, 0],
+                [0, 0, 0, 0]
+            ]
+        ],
+        [ // O
+            [
+                [0, 4, 4, 0],
+                [0, 4, 4, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 4, 4, 0],
+                [0, 4, 4, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 4, 4, 0],
+                [0, 4, 4, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 4, 4, 0],
+                [0, 4, 4, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ]
+        ],
+        [ // S
+            [
+                [0, 5, 5, 0],
+                [5, 5, 0, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 5, 0, 0],
+                [0, 5, 5, 0],
+                [0, 0, 5, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 0, 0],
+                [0, 5, 5, 0],
+                [5, 5, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [5, 0, 0, 0],
+                [5, 5, 0, 0],
+                [0, 5, 0, 0],
+                [0, 0, 0, 0]
+            ]
+        ],
+        [ // Z
+            [
+                [6, 6, 0, 0],
+                [0, 6, 6, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 6, 0],
+                [0, 6, 6, 0],
+                [0, 6, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 0, 0],
+                [6, 6, 0, 0],
+                [0, 6, 6, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 6, 0, 0],
+                [6, 6, 0, 0],
+                [6, 0, 0, 0],
+                [0, 0, 0, 0]
+            ]
+        ],
+        [ // T
+            [
+                [0, 7, 0, 0],
+                [7, 7, 7, 0],
+                [0, 0, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 7, 0, 0],
+                [0, 7, 7, 0],
+                [0, 7, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 0, 0, 0],
+                [7, 7, 7, 0],
+                [0, 7, 0, 0],
+                [0, 0, 0, 0]
+            ],
+            [
+                [0, 7, 0, 0],
+                [7, 7, 0, 0],
+                [0, 7, 0, 0],
+                [0, 0, 0, 0]
+            ]
+        ]
+    ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockClock;
+
+    fn fill_row(board: &mut Board, y: usize) {
+        for column in board.iter_mut() {
+            column[y] = 1;
+        }
+    }
+
+    #[test]
+    fn full_line_is_cleared() {
+        let mut board = [[0; HEIGHT]; WIDTH];
+        fill_row(&mut board, 0);
+        board[3][1] = 2;
+        assert_eq!(clear_lines(&mut board), 1);
+        // what was above falls down one row
+        assert_eq!(board[3][0], 2);
+        assert_eq!(board[0][0], 0);
+        assert_eq!(board[3][1], 0);
+    }
+
+    #[test]
+    fn lines_apart_are_all_cleared() {
+        let mut board = [[0; HEIGHT]; WIDTH];
+        fill_row(&mut board, 0);
+        board[5][1] = 4;
+        fill_row(&mut board, 2);
+        fill_row(&mut board, 3);
+        assert_eq!(clear_lines(&mut board), 3);
+        assert_eq!(board[5][0], 4);
+        assert!((0..WIDTH).all(|x| board[x][1] == 0));
+    }
+
+    #[test]
+    fn partial_line_stays() {
+        let mut board = [[0; HEIGHT]; WIDTH];
+        fill_row(&mut board, 0);
+        board[9][0] = 0;
+        assert_eq!(clear_lines(&mut board), 0);
+        assert_eq!(board[0][0], 1);
+    }
+
+    #[test]
+    fn pieces_stay_on_the_board() {
+        let mut board = [[0; HEIGHT]; WIDTH];
+        let o = name_to_index('O');
+        assert!(fits(&board, o, 0, 3, 15));
+        // the O fills the middle two columns of its grid
+        assert!(fits(&board, o, 0, -1, 15));
+        assert!(!fits(&board, o, 0, -2, 15));
+        assert!(!fits(&board, o, 0, 8, 15));
+        assert!(!fits(&board, o, 0, 3, -4));
+        board[4][0] = 1;
+        assert!(!fits(&board, o, 0, 3, -3));
+        assert!(fits(&board, o, 0, 3, -2));
+    }
+
+    #[test]
+    fn rotations_change_the_shape() {
+        let board = [[0; HEIGHT]; WIDTH];
+        let i = name_to_index('I');
+        // flat it reaches the last column, standing it doesn't
+        assert!(!fits(&board, i, 0, 7, 15));
+        assert!(fits(&board, i, 1, 7, 15));
+    }
+
+    #[test]
+    fn score_grows_with_lines_and_level() {
+        assert_eq!(line_score(0, 1), 0);
+        assert_eq!(line_score(1, 1), 100);
+        assert_eq!(line_score(4, 1), 800);
+        assert_eq!(line_score(2, 3), 900);
+    }
+
+    #[test]
+    fn pieces_fall_faster_up_to_level_15() {
+        assert_eq!(fall_period_ms(1), 500);
+        assert!(fall_period_ms(10) < fall_period_ms(9));
+        assert_eq!(fall_period_ms(15), fall_period_ms(20));
+    }
+
+    #[test]
+    fn rng_is_the_same_for_the_same_seed() {
+        let (a, b) = (SimpleRng::new(42), SimpleRng::new(42));
+        let first = a.next_u32();
+        assert_eq!(first, b.next_u32());
+        assert_ne!(first, a.next_u32());
+        assert_eq!(SimpleRng::new(0).next_u32(), 1013904223);
+    }
+
+    #[test]
+    fn seed_comes_from_minutes_and_seconds() {
+        assert_eq!(seed(&MockClock { time: (13, 7, 42) }), 742);
+        assert_eq!(seed(&MockClock { time: (0, 59, 59) }), 5959);
+    }
+}
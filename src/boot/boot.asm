@@ -3,13 +3,20 @@ global stack_bottom
 global stack_top
 extern k_main
 
-section .text
+KERNEL_VIRTUAL_BASE equ 0xC0000000
+KERNEL_PAGE_INDEX equ KERNEL_VIRTUAL_BASE >> 22
+BOOT_MAPPED_PAGES equ 4 ; 4 MiB pages, the first 16 MiB of RAM
+
+; GRUB jumps here with paging disabled, so until higher_half everything runs
+; at its physical address and must live in the low .boot sections
+section .boot_text progbits alloc exec nowrite align=16
 bits 32
 start:
-    mov esp, stack_top
+    mov esp, stack_top - KERNEL_VIRTUAL_BASE
     call check_multiboot
-    call k_main
-    hlt
+    call setup_boot_paging
+    lea ecx, [higher_half]
+    jmp ecx
 
 check_multiboot:
     ; check the bootloader wrote its magic value in eax before loading our kernel
@@ -22,6 +29,30 @@ check_multiboot:
     mov al, "0"
     jmp error
 
+; map the first 16 MiB twice: at 0 so the next few instructions still work,
+; and at 3 GiB where the kernel is linked. paging::init_paging replaces this.
+setup_boot_paging:
+    mov ecx, 0
+.map_page:
+    mov edx, ecx
+    shl edx, 22
+    or edx, 0x83 ; present, writable, 4 MiB page
+    mov [boot_page_directory + ecx * 4], edx
+    mov [boot_page_directory + KERNEL_PAGE_INDEX * 4 + ecx * 4], edx
+    inc ecx
+    cmp ecx, BOOT_MAPPED_PAGES
+    jne .map_page
+
+    mov ecx, cr4
+    or ecx, 0x10 ; PSE, enables 4 MiB pages
+    mov cr4, ecx
+    mov ecx, boot_page_directory
+    mov cr3, ecx
+    mov ecx, cr0
+    or ecx, 0x80000000 ; PG
+    mov cr0, ecx
+    ret
+
 error:
     mov dword [0xb8000], 0x4f524f45
     mov dword [0xb8004], 0x4f3a4f52
@@ -29,7 +60,23 @@ error:
     mov byte  [0xb800a], al
     hlt
 
-section .bss
+section .boot_bss nobits alloc write align=4096
+boot_page_directory:
+    resb 4096
+
+section .text
+higher_half:
+    mov esp, stack_top
+    push ebx ; physical address of the multiboot2 information structure
+    xor ebp, ebp ; the end of the frame pointer chain, for backtraces
+    call k_main
+    hlt
+
+section .bss nobits alloc noexec write align=4096
+global stack_guard
+; left unmapped by paging::init_paging so that overflowing the stack faults
+stack_guard:
+    resb 4096
 stack_bottom:
     resb 1048576
 stack_top:
@@ -0,0 +1,107 @@
+global isr_stub_table
+global interrupt_return
+global isr128
+extern interrupt_handler
+
+section .text
+bits 32
+
+; some exceptions push an error code, the others get a dummy one so that
+; every handler hands the same frame layout to interrupt_handler
+%macro ISR_NO_ERR 1
+isr%1:
+    push dword 0
+    push dword %1
+    jmp isr_common
+%endmacro
+
+%macro ISR_ERR 1
+isr%1:
+    push dword %1
+    jmp isr_common
+%endmacro
+
+ISR_NO_ERR 0    ; Divide Error
+ISR_NO_ERR 1    ; Debug
+ISR_NO_ERR 2    ; Non Maskable Interrupt
+ISR_NO_ERR 3    ; Breakpoint
+ISR_NO_ERR 4    ; Overflow
+ISR_NO_ERR 5    ; Bound Range Exceeded
+ISR_NO_ERR 6    ; Invalid Opcode
+ISR_NO_ERR 7    ; Device Not Available
+ISR_ERR    8    ; Double Fault
+ISR_NO_ERR 9    ; Coprocessor Segment Overrun
+ISR_ERR    10   ; Invalid TSS
+ISR_ERR    11   ; Segment Not Present
+ISR_ERR    12   ; Stack-Segment Fault
+ISR_ERR    13   ; General Protection Fault
+ISR_ERR    14   ; Page Fault
+ISR_NO_ERR 15   ; Reserved
+ISR_NO_ERR 16   ; x87 Floating-Point Exception
+ISR_ERR    17   ; Alignment Check
+ISR_NO_ERR 18   ; Machine Check
+ISR_NO_ERR 19   ; SIMD Floating-Point Exception
+ISR_NO_ERR 20   ; Virtualization Exception
+ISR_ERR    21   ; Control Protection Exception
+ISR_NO_ERR 22   ; Reserved
+ISR_NO_ERR 23   ; Reserved
+ISR_NO_ERR 24   ; Reserved
+ISR_NO_ERR 25   ; Reserved
+ISR_NO_ERR 26   ; Reserved
+ISR_NO_ERR 27   ; Reserved
+ISR_NO_ERR 28   ; Hypervisor Injection Exception
+ISR_ERR    29   ; VMM Communication Exception
+ISR_ERR    30   ; Security Exception
+ISR_NO_ERR 31   ; Reserved
+
+; hardware interrupts, remapped by the PIC right after the exceptions
+ISR_NO_ERR 32   ; IRQ0
+ISR_NO_ERR 33   ; IRQ1
+ISR_NO_ERR 34   ; IRQ2
+ISR_NO_ERR 35   ; IRQ3
+ISR_NO_ERR 36   ; IRQ4
+ISR_NO_ERR 37   ; IRQ5
+ISR_NO_ERR 38   ; IRQ6
+ISR_NO_ERR 39   ; IRQ7
+ISR_NO_ERR 40   ; IRQ8
+ISR_NO_ERR 41   ; IRQ9
+ISR_NO_ERR 42   ; IRQ10
+ISR_NO_ERR 43   ; IRQ11
+ISR_NO_ERR 44   ; IRQ12
+ISR_NO_ERR 45   ; IRQ13
+ISR_NO_ERR 46   ; IRQ14
+ISR_NO_ERR 47   ; IRQ15
+
+ISR_NO_ERR 128  ; system calls
+
+isr_common:
+    pusha
+    push ds
+    push es
+    push fs
+    push gs
+    mov ax, 0x10 ; kernel data segment
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+    cld
+    push esp ; pointer to the InterruptFrame we just built
+    call interrupt_handler
+    add esp, 4
+interrupt_return:
+    pop gs
+    pop fs
+    pop es
+    pop ds
+    popa
+    add esp, 8 ; vector and error code
+    iret
+
+section .data
+isr_stub_table:
+%assign i 0
+%rep 48
+    dd isr%+i
+%assign i i+1
+%endrep
@@ -0,0 +1,176 @@
+; programs copied into user pages and run in ring 3, they must be
+; position independent since they are not linked where they run.
+; system calls: eax = number, ebx, ecx, edx... = arguments, see syscall.rs
+global user_test_start
+global user_test_end
+global user_fork_test_start
+global user_fork_test_end
+global user_signal_test_start
+global user_signal_test_end
+
+SYS_EXIT	equ 1
+SYS_WRITE	equ 2
+SYS_GETPID	equ 6
+SYS_FORK	equ 7
+SYS_WAITPID	equ 8
+SYS_GETPPID	equ 9
+SYS_KILL	equ 11
+SYS_SIGNAL	equ 12
+SYS_SIGPROCMASK	equ 14
+
+SIGUSR1	equ 10
+SIGTERM	equ 15
+SIG_IGN	equ 1
+SIG_BLOCK	equ 0
+SIG_UNBLOCK	equ 1
+
+section .rodata
+bits 32
+
+; says hello, sums 1..100 and exits with 42 if it has a pid
+user_test_start:
+    call .message_end
+.message:
+    db "Hello from ring 3!", 10
+.message_end:
+    pop ebx ; address of .message
+    mov eax, SYS_WRITE
+    mov ecx, .message_end - .message
+    int 0x80
+    xor eax, eax
+    mov ecx, 100
+.sum:
+    add eax, ecx
+    loop .sum
+    push eax
+    mov eax, SYS_GETPID
+    int 0x80
+    mov ebx, 42
+    test eax, eax
+    jnz .exit
+    mov ebx, 1
+.exit:
+    mov eax, SYS_EXIT
+    int 0x80
+    jmp $
+user_test_end:
+
+; forks, the child exits with 42 if getppid gives the parent's pid, and
+; the parent exits with 0 if waitpid reaps that child with that status
+user_fork_test_start:
+    mov eax, SYS_GETPID
+    int 0x80
+    mov esi, eax ; our pid, the child inherits it in esi
+    mov eax, SYS_FORK
+    int 0x80
+    test eax, eax
+    js .failed
+    jz .child
+    mov edi, eax ; child pid
+    sub esp, 4
+    mov eax, SYS_WAITPID
+    mov ebx, edi
+    mov ecx, esp
+    int 0x80
+    cmp eax, edi
+    jne .failed
+    cmp dword [esp], 42 << 8 ; exited with 42
+    jne .failed
+    mov eax, SYS_EXIT
+    xor ebx, ebx
+    int 0x80
+.child:
+    mov eax, SYS_GETPPID
+    int 0x80
+    mov ebx, 42
+    cmp eax, esi
+    je .child_exit
+    mov ebx, 1
+.child_exit:
+    mov eax, SYS_EXIT
+    int 0x80
+.failed:
+    mov eax, SYS_EXIT
+    mov ebx, 1
+    int 0x80
+    jmp $
+user_fork_test_end:
+
+; catches SIGUSR1, then blocks and ignores it, and finally kills itself
+; with SIGTERM, which is what the shell expects. The program is copied to
+; writable pages so the handler counts its calls right after its code.
+user_signal_test_start:
+    call .base
+.base:
+    pop ebp ; the handler and its counter are found from here
+    mov eax, SYS_SIGNAL
+    mov ebx, SIGUSR1
+    lea ecx, [ebp + .handler - .base]
+    int 0x80
+    test eax, eax ; the previous handler was SIG_DFL
+    jnz .failed
+    mov eax, SYS_GETPID
+    int 0x80
+    mov esi, eax
+    ; handled right as kill returns, its result must survive the handler
+    mov eax, SYS_KILL
+    mov ebx, esi
+    mov ecx, SIGUSR1
+    int 0x80
+    test eax, eax
+    jnz .failed
+    cmp dword [ebp + .count - .base], 1
+    jne .failed
+    ; blocked, it stays pending until unblocked
+    push dword 1 << SIGUSR1
+    mov eax, SYS_SIGPROCMASK
+    mov ebx, SIG_BLOCK
+    mov ecx, esp
+    xor edx, edx
+    int 0x80
+    mov eax, SYS_KILL
+    mov ebx, esi
+    mov ecx, SIGUSR1
+    int 0x80
+    cmp dword [ebp + .count - .base], 1
+    jne .failed
+    mov eax, SYS_SIGPROCMASK
+    mov ebx, SIG_UNBLOCK
+    mov ecx, esp
+    xor edx, edx
+    int 0x80
+    cmp dword [ebp + .count - .base], 2
+    jne .failed
+    ; ignored, it is discarded
+    mov eax, SYS_SIGNAL
+    mov ebx, SIGUSR1
+    mov ecx, SIG_IGN
+    int 0x80
+    mov eax, SYS_KILL
+    mov ebx, esi
+    mov ecx, SIGUSR1
+    int 0x80
+    cmp dword [ebp + .count - .base], 2
+    jne .failed
+    ; the default action, does not return
+    mov eax, SYS_KILL
+    mov ebx, esi
+    mov ecx, SIGTERM
+    int 0x80
+.failed:
+    mov eax, SYS_EXIT
+    mov ebx, 1
+    int 0x80
+    jmp $
+.handler:
+    cmp dword [esp + 4], SIGUSR1
+    jne .handler_return
+    call .handler_base
+.handler_base:
+    pop eax
+    inc dword [eax + .count - .handler_base]
+.handler_return:
+    ret
+.count:
+    dd 0
+user_signal_test_end:
@@ -12,3 +12,23 @@ load_segment_registers:
 	jmp 0x08:flush_cs ; 0x08 - an offset into GDT for the second (kernel code segment) record. 
 flush_cs:
 	ret
+
+global switch_context
+
+; void switch_context(u32 *old_esp, u32 new_esp)
+; saves the callee saved registers on the current stack, stores esp in
+; *old_esp and resumes the task whose saved stack is at new_esp
+switch_context:
+	mov eax, [esp + 4]
+	mov edx, [esp + 8]
+	push ebp
+	push ebx
+	push esi
+	push edi
+	mov [eax], esp
+	mov esp, edx
+	pop edi
+	pop esi
+	pop ebx
+	pop ebp
+	ret
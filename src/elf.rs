@@ -0,0 +1,118 @@
+use core::mem::size_of;
+
+// ELF32 for i386, see the System V ABI i386 supplement
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_386: u16 = 3;
+
+pub const PT_LOAD: u32 = 1;
+pub const PF_W: u32 = 2; // there is no way to refuse execution with 32 bit paging
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Elf32Header {
+    ident: [u8; 16],
+    typ: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    phoff: u32,
+    shoff: u32,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub typ: u32,
+    pub offset: u32,
+    pub vaddr: u32,
+    pub paddr: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+    pub flags: u32,
+    pub align: u32,
+}
+
+impl ProgramHeader {
+    pub fn is_writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+}
+
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    header: Elf32Header,
+}
+
+impl<'a> ElfFile<'a> {
+    // only statically linked i386 executables, the errors are for the user
+    pub fn parse(data: &'a [u8]) -> Result<ElfFile<'a>, &'static str> {
+        if data.len() < size_of::<Elf32Header>() {
+            return Err("file too small");
+        }
+        let header = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const Elf32Header) };
+        if header.ident[..4] != ELF_MAGIC {
+            return Err("not an ELF file");
+        }
+        if header.ident[4] != ELFCLASS32 || header.ident[5] != ELFDATA2LSB || header.ident[6] != EV_CURRENT {
+            return Err("not a 32 bit little endian ELF file");
+        }
+        if header.typ != ET_EXEC {
+            return Err("not an executable");
+        }
+        if header.machine != EM_386 {
+            return Err("not an i386 program");
+        }
+        if header.phentsize as usize != size_of::<ProgramHeader>() {
+            return Err("bad program header size");
+        }
+        // a usize is 32 bits, these sums can go around
+        let table_end = (header.phnum as usize).checked_mul(size_of::<ProgramHeader>())
+            .and_then(|size| size.checked_add(header.phoff as usize));
+        if table_end.is_none_or(|end| end > data.len()) {
+            return Err("program headers past the end of the file");
+        }
+        let elf = ElfFile { data, header };
+        for segment in elf.program_headers().filter(|ph| ph.typ == PT_LOAD) {
+            if segment.filesz > segment.memsz {
+                return Err("segment bigger in the file than in memory");
+            }
+            let segment_end = (segment.offset as usize).checked_add(segment.filesz as usize);
+            if segment_end.is_none_or(|end| end > data.len()) {
+                return Err("segment past the end of the file");
+            }
+            if segment.vaddr.checked_add(segment.memsz).is_none() {
+                return Err("segment past the end of memory");
+            }
+        }
+        Ok(elf)
+    }
+
+    pub fn entry(&self) -> u32 {
+        self.header.entry
+    }
+
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + '_ {
+        let table = self.header.phoff as usize;
+        (0..self.header.phnum as usize).map(move |index| unsafe {
+            let addr = self.data.as_ptr().add(table + index * size_of::<ProgramHeader>());
+            core::ptr::read_unaligned(addr as *const ProgramHeader)
+        })
+    }
+
+    pub fn segment_data(&self, segment: &ProgramHeader) -> &'a [u8] {
+        let start = segment.offset as usize;
+        &self.data[start..start + segment.filesz as usize]
+    }
+}
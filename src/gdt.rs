@@ -1,43 +1,133 @@
 extern crate lazy_static; 
 
 use core::arch::asm;
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut};
+
+use crate::paging::phys_to_virt;
+use crate::println;
+use kfs_core::gdt::GdtEntry;
 
 extern {
     fn load_segment_registers();
 }
 
-#[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct GdtEntry {
-    pub limit_low: u16,
-    pub base_low: u16,
-    pub base_middle: u8,
-    pub access: u8,
-    pub granularity: u8,
-    pub base_high: u8,
-}
-
 #[repr(C, packed)]
 pub struct GdtR {
     pub size:u16,
     pub addr:u32,
 }
 
-impl GdtEntry {
-    pub fn new(base: u32 , limit: u32, access: u8, other:u8) -> GdtEntry {
-        GdtEntry {
-            limit_low: (limit & 0xffff) as u16,
-            base_low: (base & 0xffff) as u16,
-            base_middle: ((base >> 16) & 0xff) as u8,
-            access: access,
-            granularity: (other & 0xf0) | (((limit >> 16) & 0x0f) as u8),
-            base_high: ((base >> 24) & 0xff) as u8,
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const TSS_SELECTOR: u16 = 0x38;
+pub const DOUBLE_FAULT_TSS_SELECTOR: u16 = 0x40;
+pub const GDT_ENTRIES: usize = 9;
+
+pub const GDT_NAMES: [&str; GDT_ENTRIES] = [
+    "NULL",
+    "Kernel Code",
+    "Kernel Data",
+    "Kernel Stack",
+    "User Code",
+    "User Data",
+    "User Stack",
+    "Task State Segment",
+    "Double Fault TSS",
+];
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct TaskStateSegment {
+    pub prev_task: u32,
+    pub esp0: u32,
+    pub ss0: u32,
+    pub esp1: u32,
+    pub ss1: u32,
+    pub esp2: u32,
+    pub ss2: u32,
+    pub cr3: u32,
+    pub eip: u32,
+    pub eflags: u32,
+    pub eax: u32,
+    pub ecx: u32,
+    pub edx: u32,
+    pub ebx: u32,
+    pub esp: u32,
+    pub ebp: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub es: u32,
+    pub cs: u32,
+    pub ss: u32,
+    pub ds: u32,
+    pub fs: u32,
+    pub gs: u32,
+    pub ldt: u32,
+    pub trap: u16,
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    const fn new() -> TaskStateSegment {
+        TaskStateSegment {
+            prev_task: 0, esp0: 0, ss0: 0, esp1: 0, ss1: 0, esp2: 0, ss2: 0,
+            cr3: 0, eip: 0, eflags: 0, eax: 0, ecx: 0, edx: 0, ebx: 0,
+            esp: 0, ebp: 0, esi: 0, edi: 0, es: 0, cs: 0, ss: 0, ds: 0, fs: 0, gs: 0,
+            ldt: 0, trap: 0, iomap_base: 0,
         }
     }
 }
 
+// the CPU saves the interrupted task in TSS when switching to DOUBLE_FAULT_TSS
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+static mut DOUBLE_FAULT_TSS: TaskStateSegment = TaskStateSegment::new();
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 16 * 1024;
+
+#[repr(C, align(16))]
+struct DoubleFaultStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: DoubleFaultStack = DoubleFaultStack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+extern "C" {
+    static stack_top: u8;
+}
+
+fn tss_descriptor(tss: *const TaskStateSegment) -> GdtEntry {
+    // present, DPL 0, available 32 bit TSS, byte granularity
+    GdtEntry::new(tss as u32, size_of::<TaskStateSegment>() as u32 - 1, 0x89, 0x00)
+}
+
+fn gdt_address() -> *mut GdtEntry {
+    phys_to_virt(0x800) as *mut GdtEntry
+}
+
+pub fn read_entry(index: usize) -> GdtEntry {
+    unsafe { core::ptr::read(gdt_address().add(index)) }
+}
+
+// (name, value) of the segment registers and task register right now
+pub fn current_selectors() -> [(&'static str, u16); 7] {
+    let (cs, ds, ss, es, fs, gs, tr): (u16, u16, u16, u16, u16, u16, u16);
+    unsafe {
+        asm!(
+            "mov {0:x}, cs",
+            "mov {1:x}, ds",
+            "mov {2:x}, ss",
+            "mov {3:x}, es",
+            "mov {4:x}, fs",
+            "mov {5:x}, gs",
+            "str {6:x}",
+            out(reg) cs, out(reg) ds, out(reg) ss, out(reg) es, out(reg) fs, out(reg) gs, out(reg) tr,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+    [("CS", cs), ("DS", ds), ("SS", ss), ("ES", es), ("FS", fs), ("GS", gs), ("TR", tr)]
+}
+
 pub fn init_gdt() {
-    let gdt: [GdtEntry; 7] = [
+    let gdt: [GdtEntry; GDT_ENTRIES] = [
         GdtEntry::new(0x0, 0x0, 0x0, 0x0),          // NULL
         GdtEntry::new(0x0, 0xFFFFF, 0x9B, 0xCF),    // Kernel Code
         GdtEntry::new(0x0, 0xFFFFF, 0x93, 0xCF),    // Kernel Data
@@ -45,15 +135,17 @@ pub fn init_gdt() {
         GdtEntry::new(0x0, 0xFFFFF, 0xFA, 0xCF),    // User Code
         GdtEntry::new(0x0, 0xFFFFF, 0xF2, 0xCF),    // User Data
         GdtEntry::new(0x0, 0x0, 0xF8, 0xCF),        // User Stack
+        tss_descriptor(addr_of!(TSS)),              // Task State Segment
+        tss_descriptor(addr_of!(DOUBLE_FAULT_TSS)), // Double Fault TSS
     ];
-    let dest_addr = 0x800 as *mut GdtEntry;
+    let dest_addr = gdt_address();
     for (index, entry) in gdt.iter().enumerate() {
         unsafe {
             let dest_entry = dest_addr.offset(index as isize);
             core::ptr::write(dest_entry, *entry);
         }
     }
-    let gdtr = GdtR {size: gdt.len() as u16 * core::mem::size_of::<GdtEntry>() as u16, addr: dest_addr as u32};
+    let gdtr = GdtR {size: gdt.len() as u16 * size_of::<GdtEntry>() as u16, addr: dest_addr as u32};
     unsafe { 
         asm!(
             "lgdt [{0}]",
@@ -64,3 +156,67 @@ pub fn init_gdt() {
     }
 }
 
+
+fn read_cr3() -> u32 {
+    let cr3: u32;
+    unsafe {
+        asm!("mov {0:e}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+    cr3
+}
+
+// must run once paging is set up: the double fault task gets the current CR3
+pub fn init_tss() {
+    unsafe {
+        let tss = &mut *addr_of_mut!(TSS);
+        tss.ss0 = KERNEL_DATA_SELECTOR as u32;
+        tss.esp0 = &stack_top as *const u8 as u32;
+        tss.iomap_base = size_of::<TaskStateSegment>() as u16;
+
+        let double_fault = &mut *addr_of_mut!(DOUBLE_FAULT_TSS);
+        double_fault.cr3 = read_cr3();
+        double_fault.eip = double_fault_task as extern "C" fn() -> ! as usize as u32;
+        double_fault.esp = addr_of!(DOUBLE_FAULT_STACK) as u32 + DOUBLE_FAULT_STACK_SIZE as u32;
+        double_fault.eflags = 0x2; // interrupts disabled
+        double_fault.cs = KERNEL_CODE_SELECTOR as u32;
+        double_fault.ss = KERNEL_DATA_SELECTOR as u32;
+        double_fault.ds = KERNEL_DATA_SELECTOR as u32;
+        double_fault.es = KERNEL_DATA_SELECTOR as u32;
+        double_fault.fs = KERNEL_DATA_SELECTOR as u32;
+        double_fault.gs = KERNEL_DATA_SELECTOR as u32;
+        double_fault.iomap_base = size_of::<TaskStateSegment>() as u16;
+
+        asm!("ltr {0:x}", in(reg) TSS_SELECTOR, options(nostack, preserves_flags));
+    }
+}
+
+// stack used by the CPU when an interrupt brings us from ring 3 to ring 0
+pub fn set_kernel_stack(esp0: u32) {
+    unsafe { (*addr_of_mut!(TSS)).esp0 = esp0 };
+}
+
+pub fn kernel_stack() -> u32 {
+    unsafe { (*addr_of!(TSS)).esp0 }
+}
+
+// reached through the task gate at vector 8, on its own stack, so even a
+// kernel stack overflow ends up here. The faulting state was saved in TSS.
+extern "C" fn double_fault_task() -> ! {
+    let tss = unsafe { *addr_of!(TSS) };
+    unsafe { crate::vga_buffer::WRITER.force_unlock() };
+    let (eip, esp, ebp, eflags, cr3) = (tss.eip, tss.esp, tss.ebp, tss.eflags, tss.cr3);
+    let (stack_guard, _) = crate::paging::boot_stack_guard();
+    println!("");
+    println!("EXCEPTION 8: Double Fault");
+    println!("EIP: {:#010x}  ESP: {:#010x}  EBP: {:#010x}", eip, esp, ebp);
+    println!("EFLAGS: {:#010x}  CR3: {:#010x}", eflags, cr3);
+    if esp as usize >= stack_guard && (esp as usize) < stack_guard + 2 * crate::paging::PAGE_SIZE {
+        println!("the kernel stack overflowed");
+    }
+    println!("System halted.");
+    loop {
+        unsafe {
+            asm!("cli", "hlt", options(nomem, nostack));
+        }
+    }
+}
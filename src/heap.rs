@@ -0,0 +1,247 @@
+extern crate spin;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+use self::spin::Mutex;
+
+use crate::idt::without_interrupts;
+use crate::paging::{self, PAGE_SIZE, WRITABLE};
+use crate::pmm;
+
+pub const HEAP_START: usize = 0xD000_0000;
+const HEAP_INITIAL_SIZE: usize = 1024 * 1024;
+const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+// every block is a multiple of this, which also keeps free blocks aligned
+const BLOCK_ALIGN: usize = 8;
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+// stored right before every pointer we hand out
+struct AllocHeader {
+    block_start: usize,
+    block_end: usize,
+}
+
+const MIN_BLOCK_SIZE: usize = size_of::<FreeBlock>();
+const HEADER_SIZE: usize = size_of::<AllocHeader>();
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub start: usize,
+    pub end: usize,
+    pub used: usize,
+    pub allocations: usize,
+    pub free_blocks: usize,
+}
+
+// first fit allocator over a free list sorted by address, neighbours are
+// merged back together on free
+pub struct Heap {
+    head: *mut FreeBlock,
+    start: usize,
+    end: usize,
+    used: usize,
+    allocations: usize,
+}
+
+unsafe impl Send for Heap {}
+
+impl Heap {
+    const fn empty() -> Heap {
+        Heap {
+            head: null_mut(),
+            start: 0,
+            end: 0,
+            used: 0,
+            allocations: 0,
+        }
+    }
+
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut current = self.head;
+        while !current.is_null() && (current as usize) < addr {
+            prev = current;
+            current = (*current).next;
+        }
+        let block = addr as *mut FreeBlock;
+        (*block).size = size;
+        (*block).next = current;
+        if !current.is_null() && addr + size == current as usize {
+            (*block).size += (*current).size;
+            (*block).next = (*current).next;
+        }
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let align = layout.align().max(BLOCK_ALIGN);
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut current = self.head;
+        while !current.is_null() {
+            let block_start = current as usize;
+            let block_end = block_start + (*current).size;
+            let ptr = align_up(block_start + HEADER_SIZE, align);
+            let alloc_end = align_up(ptr + layout.size().max(1), BLOCK_ALIGN);
+            if alloc_end <= block_end {
+                let next = (*current).next;
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next = next;
+                }
+                // give back whatever is big enough to be a block on its own
+                let mut start = block_start;
+                if ptr - HEADER_SIZE - block_start >= MIN_BLOCK_SIZE {
+                    start = ptr - HEADER_SIZE;
+                    self.add_free_region(block_start, start - block_start);
+                }
+                let mut end = block_end;
+                if block_end - alloc_end >= MIN_BLOCK_SIZE {
+                    end = alloc_end;
+                    self.add_free_region(alloc_end, block_end - alloc_end);
+                }
+                let header = (ptr - HEADER_SIZE) as *mut AllocHeader;
+                (*header).block_start = start;
+                (*header).block_end = end;
+                self.used += end - start;
+                self.allocations += 1;
+                return ptr as *mut u8;
+            }
+            prev = current;
+            current = (*current).next;
+        }
+        null_mut()
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8) {
+        let header = (ptr as usize - HEADER_SIZE) as *const AllocHeader;
+        let (start, end) = ((*header).block_start, (*header).block_end);
+        self.used -= end - start;
+        self.allocations -= 1;
+        self.add_free_region(start, end - start);
+    }
+
+    // usable bytes behind a pointer returned by allocate
+    unsafe fn usable_size(&self, ptr: *mut u8) -> usize {
+        let header = (ptr as usize - HEADER_SIZE) as *const AllocHeader;
+        (*header).block_end - ptr as usize
+    }
+
+    fn grow(&mut self, min_size: usize) -> bool {
+        let size = align_up(min_size, PAGE_SIZE).max(HEAP_INITIAL_SIZE);
+        if self.end - self.start + size > HEAP_MAX_SIZE {
+            return false;
+        }
+        if paging::map_range(self.end, self.end + size, WRITABLE).is_err() {
+            // give back what got mapped before running out, or the next
+            // grow would find those pages already there
+            for page in (self.end..self.end + size).step_by(PAGE_SIZE) {
+                if let Some(frame) = paging::unmap_page(page) {
+                    pmm::free_frame(frame);
+                }
+            }
+            return false;
+        }
+        unsafe { self.add_free_region(self.end, size) };
+        self.end += size;
+        true
+    }
+
+    fn stats(&self) -> HeapStats {
+        let mut free_blocks = 0;
+        let mut current = self.head;
+        while !current.is_null() {
+            free_blocks += 1;
+            current = unsafe { (*current).next };
+        }
+        HeapStats {
+            start: self.start,
+            end: self.end,
+            used: self.used,
+            allocations: self.allocations,
+            free_blocks,
+        }
+    }
+}
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap::empty());
+
+pub struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| {
+            let mut heap = HEAP.lock();
+            let ptr = heap.allocate(layout);
+            if !ptr.is_null() || !heap.grow(layout.size() + layout.align() + HEADER_SIZE) {
+                return ptr;
+            }
+            heap.allocate(layout)
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        without_interrupts(|| HEAP.lock().deallocate(ptr));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!("kernel heap exhausted: cannot allocate {} bytes (align {})", layout.size(), layout.align());
+}
+
+pub fn init_heap() {
+    without_interrupts(|| {
+        let mut heap = HEAP.lock();
+        heap.start = HEAP_START;
+        heap.end = HEAP_START;
+        if !heap.grow(HEAP_INITIAL_SIZE) {
+            panic!("cannot map the initial kernel heap");
+        }
+    });
+}
+
+pub fn kmalloc(size: usize) -> *mut u8 {
+    match Layout::from_size_align(size, BLOCK_ALIGN) {
+        Ok(layout) => unsafe { ALLOCATOR.alloc(layout) },
+        Err(_) => null_mut(),
+    }
+}
+
+pub unsafe fn kfree(ptr: *mut u8) {
+    if !ptr.is_null() {
+        without_interrupts(|| HEAP.lock().deallocate(ptr));
+    }
+}
+
+pub unsafe fn ksize(ptr: *mut u8) -> usize {
+    if ptr.is_null() {
+        return 0;
+    }
+    without_interrupts(|| HEAP.lock().usable_size(ptr))
+}
+
+pub fn heap_stats() -> HeapStats {
+    without_interrupts(|| HEAP.lock().stats())
+}
@@ -0,0 +1,253 @@
+use core::arch::asm;
+use core::ptr::addr_of;
+use core::ptr::addr_of_mut;
+
+use crate::gdt::{DOUBLE_FAULT_TSS_SELECTOR, KERNEL_CODE_SELECTOR};
+use crate::paging;
+use crate::pic;
+use crate::signal;
+use crate::syscall;
+use crate::usermode;
+use crate::println;
+
+extern "C" {
+    static isr_stub_table: [u32; 48];
+    fn isr128();
+}
+
+pub const IDT_ENTRIES: usize = 256;
+const INTERRUPT_GATE: u8 = 0x8E; // present, DPL 0, 32-bit interrupt gate
+const TASK_GATE: u8 = 0x85; // present, DPL 0, task gate
+// present, DPL 3 so int 0x80 works from ring 3, and a trap gate so system
+// calls that wait (sleep, read_key) still get interrupts
+const USER_TRAP_GATE: u8 = 0xEF;
+pub const SYSCALL_VECTOR: usize = 0x80;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct IdtEntry {
+    pub offset_low: u16,
+    pub selector: u16,
+    pub zero: u8,
+    pub type_attr: u8,
+    pub offset_high: u16,
+}
+
+#[repr(C, packed)]
+pub struct IdtR {
+    pub size:u16,
+    pub addr:u32,
+}
+
+impl IdtEntry {
+    pub const fn missing() -> IdtEntry {
+        IdtEntry {
+            offset_low: 0,
+            selector: 0,
+            zero: 0,
+            type_attr: 0,
+            offset_high: 0,
+        }
+    }
+
+    pub fn new(offset: u32, selector: u16, type_attr: u8) -> IdtEntry {
+        IdtEntry {
+            offset_low: (offset & 0xffff) as u16,
+            selector,
+            zero: 0,
+            type_attr,
+            offset_high: ((offset >> 16) & 0xffff) as u16,
+        }
+    }
+}
+
+static mut IDT: [IdtEntry; IDT_ENTRIES] = [IdtEntry::missing(); IDT_ENTRIES];
+
+pub type IrqHandler = fn(&mut InterruptFrame);
+
+static mut IRQ_HANDLERS: [Option<IrqHandler>; 16] = [None; 16];
+
+// Layout of what isr_common (interrupts.asm) pushes on the stack,
+// lowest address first
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptFrame {
+    pub gs: u32,
+    pub fs: u32,
+    pub es: u32,
+    pub ds: u32,
+    pub edi: u32,
+    pub esi: u32,
+    pub ebp: u32,
+    pub esp: u32,
+    pub ebx: u32,
+    pub edx: u32,
+    pub ecx: u32,
+    pub eax: u32,
+    pub vector: u32,
+    pub error_code: u32,
+    pub eip: u32,
+    pub cs: u32,
+    pub eflags: u32,
+    // only pushed by the CPU when coming from ring 3
+    pub user_esp: u32,
+    pub user_ss: u32,
+}
+
+const EXCEPTION_NAMES: [&str; 32] = [
+    "Divide Error",
+    "Debug",
+    "Non Maskable Interrupt",
+    "Breakpoint",
+    "Overflow",
+    "Bound Range Exceeded",
+    "Invalid Opcode",
+    "Device Not Available",
+    "Double Fault",
+    "Coprocessor Segment Overrun",
+    "Invalid TSS",
+    "Segment Not Present",
+    "Stack-Segment Fault",
+    "General Protection Fault",
+    "Page Fault",
+    "Reserved",
+    "x87 Floating-Point Exception",
+    "Alignment Check",
+    "Machine Check",
+    "SIMD Floating-Point Exception",
+    "Virtualization Exception",
+    "Control Protection Exception",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Hypervisor Injection Exception",
+    "VMM Communication Exception",
+    "Security Exception",
+    "Reserved",
+];
+
+pub fn set_gate(vector: usize, handler: u32, type_attr: u8) {
+    unsafe {
+        (*addr_of_mut!(IDT))[vector] = IdtEntry::new(handler, KERNEL_CODE_SELECTOR, type_attr);
+    }
+}
+
+// the offset is ignored, the CPU switches to the task described by the TSS
+pub fn set_task_gate(vector: usize, tss_selector: u16) {
+    unsafe {
+        (*addr_of_mut!(IDT))[vector] = IdtEntry::new(0, tss_selector, TASK_GATE);
+    }
+}
+
+pub fn idt_address() -> u32 {
+    addr_of!(IDT) as u32
+}
+
+pub fn init_idt() {
+    for (vector, &handler) in unsafe { (*addr_of!(isr_stub_table)).iter() }.enumerate() {
+        set_gate(vector, handler, INTERRUPT_GATE);
+    }
+    // a double fault often means a broken stack, handle it on a fresh one
+    set_task_gate(8, DOUBLE_FAULT_TSS_SELECTOR);
+    set_gate(SYSCALL_VECTOR, isr128 as unsafe extern "C" fn() as usize as u32, USER_TRAP_GATE);
+    let idtr = IdtR {size: (IDT_ENTRIES * core::mem::size_of::<IdtEntry>()) as u16 - 1, addr: idt_address()};
+    unsafe {
+        asm!(
+            "lidt [{0}]",
+            in(reg) &idtr,
+            options(readonly, nostack, preserves_flags)
+        );
+    }
+}
+
+pub fn register_irq_handler(irq: u8, handler: IrqHandler) {
+    without_interrupts(|| unsafe {
+        (*addr_of_mut!(IRQ_HANDLERS))[irq as usize] = Some(handler);
+    });
+}
+
+pub fn enable_interrupts() {
+    unsafe { asm!("sti", options(nomem, nostack)) };
+}
+
+pub fn disable_interrupts() {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+}
+
+pub fn interrupts_enabled() -> bool {
+    let eflags: u32;
+    unsafe {
+        asm!("pushfd", "pop {0:e}", out(reg) eflags, options(nomem, preserves_flags));
+    }
+    eflags & (1 << 9) != 0
+}
+
+pub fn without_interrupts<F: FnOnce() -> R, R>(f: F) -> R {
+    let enabled = interrupts_enabled();
+    if enabled {
+        disable_interrupts();
+    }
+    let result = f();
+    if enabled {
+        enable_interrupts();
+    }
+    result
+}
+
+// sti only takes effect after the next instruction, so no interrupt can
+// sneak in between the two and leave us sleeping with work pending
+pub fn enable_interrupts_and_hlt() {
+    unsafe { asm!("sti", "hlt", options(nomem, nostack)) };
+}
+
+#[no_mangle]
+pub extern "C" fn interrupt_handler(frame: &mut InterruptFrame) {
+    if frame.vector == 14 {
+        paging::page_fault_handler(frame);
+    } else if frame.vector < 32 {
+        exception_handler(frame);
+    } else if frame.vector < 48 {
+        irq_handler(frame);
+    } else if frame.vector == SYSCALL_VECTOR as u32 {
+        syscall::syscall_handler(frame);
+    }
+    // signals are handled on the way back to ring 3
+    if usermode::from_user_mode(frame) {
+        signal::handle_signals(frame);
+    }
+}
+
+fn irq_handler(frame: &mut InterruptFrame) {
+    let irq = (frame.vector - pic::PIC1_OFFSET as u32) as u8;
+    if pic::is_spurious(irq) {
+        return;
+    }
+    pic::end_of_interrupt(irq);
+    if let Some(handler) = unsafe { (*addr_of!(IRQ_HANDLERS))[irq as usize] } {
+        handler(frame);
+    }
+}
+
+pub fn exception_handler(frame: &InterruptFrame) -> ! {
+    // the fault may have happened while the writer was locked
+    unsafe { crate::vga_buffer::WRITER.force_unlock() };
+    println!("");
+    println!("EXCEPTION {}: {}", frame.vector, EXCEPTION_NAMES[frame.vector as usize]);
+    println!("error code: {:#010x}", frame.error_code);
+    println!("EIP: {:#010x}  CS: {:#06x}  EFLAGS: {:#010x}", frame.eip, frame.cs, frame.eflags);
+    println!("EAX: {:#010x}  EBX: {:#010x}  ECX: {:#010x}  EDX: {:#010x}", frame.eax, frame.ebx, frame.ecx, frame.edx);
+    println!("ESI: {:#010x}  EDI: {:#010x}  EBP: {:#010x}  ESP: {:#010x}", frame.esi, frame.edi, frame.ebp, frame.esp);
+    if usermode::from_user_mode(frame) {
+        println!("User program killed.");
+        usermode::kill_faulting_process(frame);
+    }
+    println!("System halted.");
+    loop {
+        unsafe {
+            asm!("cli", "hlt", options(nomem, nostack));
+        }
+    }
+}
@@ -1,28 +1,18 @@
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::arch::asm;
+use core::sync::atomic::{AtomicU8, Ordering};
 
 use println;
 use print;
 
-use crate::{print_mem_area, tetris, vga_buffer::{self, Color, WRITER}};
-
-const PS2_DATA_PORT: u16 = 0x60;
-const PS2_STATUS_PORT: u16 = 0x64;
-
-pub fn read_status() -> u8 {
-    unsafe { inb(PS2_STATUS_PORT) }
-}
-
-pub fn read_data() -> u8 {
-    while (read_status() & 0x01) == 0 {}
-    unsafe { inb(PS2_DATA_PORT) }
-}
-
-pub fn try_read_data() -> u8 {
-    if (read_status() & 0x01) != 0 {
-        unsafe { return inb(PS2_DATA_PORT) }
-    }
-    return 0;
-}
+use crate::{gdt, heap, idt, keyboard, keymap, multiboot, paging, pmm, print_mem_area, scheduler, serial, signal, tetris, thread, timer, usermode, vga_buffer::{self, Color, WRITER}};
+use crate::keyboard::{Key, KeyEvent, KeyboardError};
+use crate::process::{ExitStatus, Pid};
+use crate::signal::KillError;
+use kfs_core::hal::PortIo;
+use kfs_core::parse::{atotenths, atousize};
+use crate::usermode::ExecError;
 
 // Low-level I/O operations
 
@@ -46,193 +36,107 @@ pub unsafe fn outb(port: u16, value: u8) {
     );
 }
 
+// the ports themselves, for kfs_core
+pub struct Ports;
 
-pub fn handle_keyboard_input(scan_code: u8) {
-    if scan_code == 72 {
-        vga_buffer::WRITER.lock().scroll_up();
-        return;
-    }
-    if scan_code == 80 {
-        vga_buffer::WRITER.lock().scroll_down();
-        return;
+impl PortIo for Ports {
+    fn read_u8(&mut self, port: u16) -> u8 {
+        unsafe { inb(port) }
     }
-    if scan_code == 75 {
-        vga_buffer::WRITER.lock().move_cursor(-1);
-    }
-    if scan_code == 77 {
-        vga_buffer::WRITER.lock().move_cursor(1);
+
+    fn write_u8(&mut self, port: u16, value: u8) {
+        unsafe { outb(port, value) }
     }
-    static mut SHIFT : u8 = 0;
-    static mut CAPS : u8 = 0;
-    if scan_code == 42 || scan_code == 54 {
-        unsafe { SHIFT = SHIFT + 1 };
+}
+
+#[cfg(test)]
+pub unsafe fn outl(port: u16, value: u32) {
+    asm!(
+        "out dx, eax",
+        in("dx") port,
+        in("eax") value,
+    );
+}
+
+
+pub fn handle_keyboard_input(event: KeyEvent) {
+    if !event.pressed || move_view(event.key) {
         return;
     }
-    if scan_code == 170 || scan_code == 182 {
-        unsafe { SHIFT = SHIFT - 1 };
+    // Ctrl+C with no program running drops the line, like in a real shell
+    if keymap::is_ctrl_c(&event) {
+        cancel_line();
         return;
     }
-    if scan_code == 58 {
-        unsafe {
-            if CAPS == 0 {
-                CAPS = 1;
-            }
-            else {
-                CAPS = 0;
-            }
-        };
-        return;
+    for c in keymap::type_key(&event) {
+        type_char(c);
     }
-    const KBD_US: [&str; 59] = [
-        "\0", // 0
-        "\x1b", // 1 - echap
-        "1", // 2
-        "2", // 3
-        "3", // 4
-        "4", // 5
-        "5", // 6
-        "6", // 7
-        "7", // 8
-        "8", // 9
-        "9", // 10
-        "0", // 11
-        "-", // 12
-        "=", // 13
-        "\x08", // 14 - delete key
-        "\t", // 15
-        "q", // 16
-        "w", // 17
-        "e", // 18
-        "r", // 19
-        "t", // 20
-        "y", // 21
-        "u", // 22
-        "i", // 23
-        "o", // 24
-        "p", // 25
-        "[", // 26
-        "]", // 27
-        "\n", // 28
-        "\0", // 29 - Control key
-        "a", // 30
-        "s", // 31
-        "d", // 32
-        "f", // 33
-        "g", // 34
-        "h", // 35
-        "j", // 36
-        "k", // 37
-        "l", // 38
-        ";", // 39
-        "'", // 40
-        "`", // 41
-        "\0", // 42 - Shift key
-        "\\", // 43
-        "z", // 44
-        "x", // 45
-        "c", // 46
-        "v", // 47
-        "b", // 48
-        "n", // 49
-        "m", // 50
-        ",", // 51
-        ".", // 52
-        "/", // 53
-        "\0", // 54 - Right Shift key
-        "\0", // 55 - *
-        "\0", // 56 - Alt key
-        " ", // 57 - Space
-        "\0", // 58 - Caps Lock
-    ];
-    const KBD_US_MAJ: [&str; 59] = [
-        "\0", // 0
-        "\x1b", // 1 - echap
-        "!", // 2
-        "@", // 3
-        "#", // 4
-        "$", // 5
-        "%", // 6
-        "^", // 7
-        "&", // 8
-        "*", // 9
-        "(", // 10
-        ")", // 11
-        "_", // 12
-        "+", // 13
-        "\x08", // 14 - delete key
-        "\t", // 15
-        "Q", // 16
-        "W", // 17
-        "E", // 18
-        "R", // 19
-        "T", // 20
-        "Y", // 21
-        "U", // 22
-        "I", // 23
-        "O", // 24
-        "P", // 25
-        "{", // 26
-        "}", // 27
-        "\n", // 28
-        "\0", // 29 - Control key
-        "A", // 30
-        "S", // 31
-        "D", // 32
-        "F", // 33
-        "G", // 34
-        "H", // 35
-        "J", // 36
-        "K", // 37
-        "L", // 38
-        ":", // 39
-        "\"", // 40
-        "~", // 41
-        "\0", // 42 - Shift key
-        "|", // 43
-        "Z", // 44
-        "X", // 45
-        "C", // 46
-        "V", // 47
-        "B", // 48
-        "N", // 49
-        "M", // 50
-        "<", // 51
-        ">", // 52
-        "?", // 53
-        "\0", // 54 - Right Shift key
-        "\0", // 55 - *
-        "\0", // 56 - Alt key
-        " ", // 57 - Space
-        "\0", // 58 - Caps Lock
-    ];
-    if (scan_code as usize) < KBD_US.len() && scan_code != '\0' as u8 {
-        if unsafe {SHIFT == 0 && CAPS == 0} {
-            print!("{}",KBD_US[scan_code as usize]);
-        }
-        else {
-            print!("{}",KBD_US_MAJ[scan_code as usize]);
-        }
-        if scan_code == 28 {
-            let cmd = WRITER.lock().get_last_line();
-            let mut tmp: [u8; 80] = [0; 80];
-            for (i, char) in cmd.iter().enumerate() {
-                tmp[i] = char.ascii;
-            }
-            let str = match core::str::from_utf8(&tmp) {
-                Ok(s) => s,
-                Err(_) => {
-                    WRITER.lock().toggle_cmd(true);
-                    println!("Unprintable characters spotted");
+}
+
+// ESC [ and a letter, for the arrows of a terminal on the serial line
+const SERIAL_NORMAL: u8 = 0;
+const SERIAL_ESCAPE: u8 = 1;
+const SERIAL_CSI: u8 = 2;
+static SERIAL_STATE: AtomicU8 = AtomicU8::new(SERIAL_NORMAL);
+
+pub fn handle_serial_input(byte: u8) {
+    match SERIAL_STATE.swap(SERIAL_NORMAL, Ordering::Relaxed) {
+        SERIAL_ESCAPE if byte == b'[' => SERIAL_STATE.store(SERIAL_CSI, Ordering::Relaxed),
+        SERIAL_CSI => {
+            let key = match byte {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                b'C' => Key::Right,
+                b'D' => Key::Left,
+                // parameters, the sequence goes on
+                b'0'..=b'9' | b';' => {
+                    SERIAL_STATE.store(SERIAL_CSI, Ordering::Relaxed);
                     return;
                 }
+                _ => return,
             };
-            if let Some(cmd) = str.strip_prefix("$>") {
-                if !cmd.trim().is_empty() {
-                    call_function(str);
-                } else {
-                    WRITER.lock().toggle_cmd(true);
-                    println!("");
-                }
+            move_view(key);
+        }
+        _ => match byte {
+            0x1b => SERIAL_STATE.store(SERIAL_ESCAPE, Ordering::Relaxed),
+            0x03 => cancel_line(),
+            // terminals send DEL for backspace and \r for enter
+            0x7f | 0x08 => type_char('\x08'),
+            b'\r' | b'\n' => type_char('\n'),
+            0x20..=0x7e => type_char(byte as char),
+            _ => {}
+        },
+    }
+}
+
+// true if the key was an arrow, which scroll or move the cursor
+fn move_view(key: Key) -> bool {
+    match key {
+        Key::Up => vga_buffer::WRITER.lock().scroll_up(),
+        Key::Down => vga_buffer::WRITER.lock().scroll_down(),
+        Key::Left => vga_buffer::WRITER.lock().move_cursor(-1),
+        Key::Right => vga_buffer::WRITER.lock().move_cursor(1),
+        _ => return false,
+    }
+    true
+}
+
+fn cancel_line() {
+    WRITER.lock().toggle_cmd(true);
+    println!("^C");
+}
+
+fn type_char(c: char) {
+    print!("{}", c);
+    if c == '\n' {
+        let cmd = WRITER.lock().get_last_line();
+        let line: String = cmd.iter().map(|char| vga_buffer::from_cp437(char.ascii)).collect();
+        if let Some(cmd) = line.strip_prefix("$>") {
+            if !cmd.trim().is_empty() {
+                call_function(&line);
+            } else {
+                WRITER.lock().toggle_cmd(true);
+                println!("");
             }
         }
     }
@@ -265,7 +169,7 @@ fn call_function (input: &str) {
                 ft_halt();
             }
             "tetris" => {
-                tetris::ft_tetris();
+                ft_tetris();
             }
             "clear" => {
                 ft_clear();
@@ -273,6 +177,51 @@ fn call_function (input: &str) {
             "gdt" => {
                 ft_gdt();
             }
+            "idt" => {
+                ft_idt();
+            }
+            "uptime" => {
+                ft_uptime();
+            }
+            "sleep" => {
+                ft_sleep(input);
+            }
+            "bootinfo" => {
+                ft_bootinfo();
+            }
+            "meminfo" => {
+                ft_meminfo();
+            }
+            "exec" => {
+                ft_exec(input);
+            }
+            "forktest" => {
+                ft_forktest();
+            }
+            "ps" => {
+                ft_ps();
+            }
+            "ring3" => {
+                ft_ring3();
+            }
+            "kbdrate" => {
+                ft_kbdrate(input);
+            }
+            "keymap" => {
+                ft_keymap(input);
+            }
+            "kill" => {
+                ft_kill(input);
+            }
+            "serial" => {
+                ft_serial(input);
+            }
+            "sigtest" => {
+                ft_sigtest();
+            }
+            "heap" => {
+                ft_heap();
+            }
             "s" => {
                 ft_switch_tab(0);
             }
@@ -318,6 +267,21 @@ fn ft_help() {
     println!("42      : Prints 42 for kfs1's subject");
     println!("clear   : Clears the screen");
     println!("gdt     : Prints the Global Descriptor Table's memory space");
+    println!("idt     : Prints the Interrupt Descriptor Table's memory space");
+    println!("uptime  : Prints the time elapsed since boot");
+    println!("sleep   : Waits for a while <...arg : milliseconds>");
+    println!("bootinfo: Prints what the bootloader told us about the machine");
+    println!("meminfo : Prints how much physical memory is used");
+    println!("heap    : Prints kernel heap statistics");
+    println!("exec    : Runs a program loaded by GRUB <...arg : path, arguments>");
+    println!("forktest: Tests fork, waitpid and exit from user mode");
+    println!("kbdrate : Prints or sets the key repeat delay and rate <...arg : ms, chars/s>");
+    println!("keymap  : Lists or changes the keyboard layout <...arg : us, fr, de, dvorak>");
+    println!("kill    : Sends a signal to a process <...arg : [-signal] pid>");
+    println!("ps      : Lists running processes and threads");
+    println!("ring3   : Runs a small test program in user mode");
+    println!("serial  : Prints or changes the serial console <...arg : on, off>");
+    println!("sigtest : Tests signal handlers, masks and default actions");
     println!("s/1/2/3 : Switch tab");
     WRITER.lock().toggle_cmd(true);
     println!("There might be other hidden features...");
@@ -364,7 +328,7 @@ fn ft_color(input: &str) {
 
 fn ft_halt() {
     unsafe {
-		asm!("hlt", options(nomem, nostack, preserves_flags));
+		asm!("cli", "hlt", options(nomem, nostack));
 	}
 }
 
@@ -387,9 +351,326 @@ fn ft_echo(input: &str) {
 
 fn ft_gdt() {
     println!("Global Descriptor Table (located at 0x800)");
-    print_mem_area(0x800 as *mut i32, 16);
+    print_mem_area(paging::phys_to_virt(0x800) as *mut i32, 20);
+    println!("-----end of gdt at 0x848------");
+    let selectors = gdt::current_selectors();
+    for index in 0..gdt::GDT_ENTRIES {
+        let entry = gdt::read_entry(index);
+        let selector = (index * 8) as u16;
+        print!("{:#06x}  {:<18} base {:#010x} limit {:#07x} access {:#04x}  ", selector, gdt::GDT_NAMES[index], entry.base(), entry.limit(), entry.access);
+        for (register, value) in selectors.iter() {
+            if index != 0 && value & !3 == selector {
+                print!("{} ", register);
+            }
+        }
+        if index != 0 && usermode::user_mode_used() && (selector == usermode::USER_CODE_SELECTOR & !3 || selector == usermode::USER_DATA_SELECTOR & !3) {
+            print!("(ring 3)");
+        }
+        println!("");
+    }
+    WRITER.lock().toggle_cmd(true);
+    println!("");
+}
+
+fn ft_idt() {
+    let addr = idt::idt_address();
+    let end = addr as usize + idt::IDT_ENTRIES * core::mem::size_of::<idt::IdtEntry>();
+    println!("Interrupt Descriptor Table (located at {:#x})", addr);
+    print_mem_area(addr as *mut i32, idt::IDT_ENTRIES * core::mem::size_of::<idt::IdtEntry>() / 4);
+    WRITER.lock().toggle_cmd(true);
+    println!("-----end of idt at {:#x}------", end);
+}
+
+fn ft_uptime() {
+    let ms = timer::uptime_ms();
+    let seconds = ms / 1000;
+    WRITER.lock().toggle_cmd(true);
+    println!("up {}:{:02}:{:02}.{:03} ({} ticks at {} Hz)", seconds / 3600, (seconds / 60) % 60, seconds % 60, ms % 1000, timer::ticks64(), timer::frequency());
+}
+
+fn ft_sleep(input: &str) {
+    let ms = input[2..].split_whitespace().nth(1).and_then(atousize);
+    WRITER.lock().toggle_cmd(true);
+    match ms {
+        Some(ms) => {
+            // the shell is a kernel thread, no signal can cut this short
+            let _ = timer::sleep_ms(ms as u32);
+            println!("");
+        }
+        None => println!("Please provide a numeric value corresponding to the milliseconds to sleep."),
+    }
+}
+
+fn ft_bootinfo() {
+    let info = match multiboot::boot_info() {
+        Some(info) => info,
+        None => {
+            WRITER.lock().toggle_cmd(true);
+            println!("No multiboot information available");
+            return;
+        }
+    };
+    println!("Multiboot2 information at {:#x} ({} bytes)", info.start_address(), info.total_size());
+    if let Some(name) = info.boot_loader_name() {
+        println!("Boot loader : {}", name);
+    }
+    if let Some(cmdline) = info.command_line() {
+        println!("Command line: {}", cmdline);
+    }
+    if let Some((lower, upper)) = info.basic_memory_info() {
+        println!("Memory      : {} KiB lower, {} KiB upper", lower, upper);
+    }
+    if let Some(memory_map) = info.memory_map() {
+        println!("Memory map  :");
+        for area in memory_map {
+            println!("    {:#012x} - {:#012x} {:?}", area.base_addr, area.base_addr + area.length, area.typ);
+        }
+    }
+    for module in info.modules() {
+        println!("Module      : {:#x} - {:#x} {}", module.start, module.end, module.cmdline);
+    }
+    if let Some(fb) = info.framebuffer() {
+        println!("Framebuffer : {:#x} {}x{} {} bpp, pitch {}, type {}", fb.addr, fb.width, fb.height, fb.bpp, fb.pitch, fb.typ);
+    }
+    if let Some(sections) = info.elf_sections() {
+        println!("ELF sections:");
+        for section in sections.filter(|s| s.addr != 0) {
+            println!("    {:<16} {:#010x} - {:#010x} type {} flags {:#x}", section.name, section.addr, section.addr + section.size, section.typ, section.flags);
+        }
+    }
+    WRITER.lock().toggle_cmd(true);
+    match info.rsdp() {
+        Some(rsdp) => match rsdp.xsdt_address {
+            Some(xsdt) => println!("ACPI RSDP   : {} rev {}, RSDT at {:#x}, XSDT at {:#x}", rsdp.oem_id, rsdp.revision, rsdp.rsdt_address, xsdt),
+            None => println!("ACPI RSDP   : {} rev {}, RSDT at {:#x}", rsdp.oem_id, rsdp.revision, rsdp.rsdt_address),
+        },
+        None => println!("ACPI RSDP   : none"),
+    }
+}
+
+fn ft_meminfo() {
+    let (total, used, free) = pmm::frame_stats();
+    let (kernel_start, kernel_end) = pmm::kernel_bounds();
+    let frame_kib = pmm::FRAME_SIZE / 1024;
+    println!("Kernel image: {:#x} - {:#x} ({} KiB)", kernel_start, kernel_end, (kernel_end - kernel_start) / 1024);
+    println!("Total frames: {:>8} ({} KiB)", total, total * frame_kib);
+    println!("Used frames : {:>8} ({} KiB)", used, used * frame_kib);
+    WRITER.lock().toggle_cmd(true);
+    println!("Free frames : {:>8} ({} KiB)", free, free * frame_kib);
+}
+
+fn ft_heap() {
+    let stats = heap::heap_stats();
+    println!("Kernel heap : {:#x} - {:#x} ({} KiB mapped)", stats.start, stats.end, (stats.end - stats.start) / 1024);
+    println!("Used        : {} bytes in {} allocations", stats.used, stats.allocations);
+    println!("Free        : {} bytes in {} blocks", stats.end - stats.start - stats.used, stats.free_blocks);
+    let ptr = heap::kmalloc(100);
+    let size = unsafe { heap::ksize(ptr) };
+    unsafe { heap::kfree(ptr) };
+    WRITER.lock().toggle_cmd(true);
+    println!("kmalloc(100): {:?}, ksize: {} bytes, freed", ptr, size);
+}
+
+// tetris gets its own process, the shell waits for it to quit
+fn ft_tetris() {
+    thread::spawn("tetris", tetris::ft_tetris).join();
+}
+
+fn ft_ps() {
+    WRITER.lock().toggle_cmd(true);
+    println!("  PID  PPID  STATE    NAME");
+    for process in scheduler::processes() {
+        let parent = match process.parent {
+            Some(pid) => pid as i64,
+            None => -1,
+        };
+        println!("{:>5} {:>5}  {:<8} {}", process.pid, parent, process.state.as_str(), process.name);
+    }
+}
+
+// starts a user program and waits for it, like a shell would
+fn run_user_program(name: &'static str, code: &[u8]) -> Option<ExitStatus> {
+    let pid = match usermode::spawn_user_program(name, code) {
+        Ok(pid) => pid,
+        Err(err) => {
+            WRITER.lock().toggle_cmd(true);
+            println!("kfs: {}: cannot load the program: {:?}", name, err);
+            return None;
+        }
+    };
+    wait_foreground(pid)
+}
+
+// Ctrl+C sends SIGINT to the program while we wait for it
+fn wait_foreground(pid: Pid) -> Option<ExitStatus> {
+    signal::set_foreground(Some(pid));
+    let status = scheduler::waitpid(Some(pid)).ok().map(|(_, status)| status);
+    signal::set_foreground(None);
+    status
+}
+
+fn print_exit_status(name: &str, status: ExitStatus) {
+    match status {
+        ExitStatus::Exited(code) => println!("{}: exited with status {}", name, code),
+        ExitStatus::Killed(number) => match signal::name(number) {
+            Some(signal) => println!("{}: killed by SIG{}", name, signal),
+            None => println!("{}: killed by signal {}", name, number),
+        },
+    }
+}
+
+fn ft_exec(input: &str) {
+    let argv: Vec<&str> = input[2..].split_whitespace().skip(1).collect();
+    let path = match argv.first() {
+        Some(path) => *path,
+        None => {
+            WRITER.lock().toggle_cmd(true);
+            println!("Please provide the path of the program to run.");
+            return;
+        }
+    };
+    let status = match usermode::spawn_program(path, &argv) {
+        Ok(pid) => wait_foreground(pid),
+        Err(err) => {
+            WRITER.lock().toggle_cmd(true);
+            match err {
+                ExecError::NotFound => println!("kfs: {}: no such program", path),
+                ExecError::InvalidExecutable(reason) => println!("kfs: {}: cannot execute: {}", path, reason),
+                ExecError::ArgumentsTooLong => println!("kfs: {}: argument list too long", path),
+                ExecError::OutOfMemory => println!("kfs: {}: out of memory", path),
+            }
+            return;
+        }
+    };
+    WRITER.lock().toggle_cmd(true);
+    match status {
+        Some(ExitStatus::Exited(0)) | None => println!(""),
+        Some(status) => print_exit_status(path, status),
+    }
+}
+
+fn ft_ring3() {
+    println!("Running the test program in ring 3...");
+    let status = run_user_program("ring3", usermode::test_program());
+    WRITER.lock().toggle_cmd(true);
+    if let Some(status) = status {
+        print_exit_status("Test program", status);
+    }
+}
+
+// the program forks, the child checks getppid and exits with 42, and
+// the parent exits with 0 only if waitpid told it exactly that
+fn ft_forktest() {
+    let status = run_user_program("forktest", usermode::fork_test_program());
+    WRITER.lock().toggle_cmd(true);
+    match status {
+        Some(ExitStatus::Exited(0)) => println!("forktest: OK"),
+        Some(status) => println!("forktest: FAILED ({:?})", status),
+        None => println!("forktest: FAILED (not started)"),
+    }
+}
+
+// the program catches, blocks and ignores SIGUSR1, then must die of SIGTERM
+fn ft_sigtest() {
+    let status = run_user_program("sigtest", usermode::signal_test_program());
+    WRITER.lock().toggle_cmd(true);
+    match status {
+        Some(ExitStatus::Killed(signal::SIGTERM)) => println!("sigtest: OK"),
+        Some(status) => println!("sigtest: FAILED ({:?})", status),
+        None => println!("sigtest: FAILED (not started)"),
+    }
+}
+
+fn ft_keymap(input: &str) {
+    let name = input[2..].split_whitespace().nth(1);
+    WRITER.lock().toggle_cmd(true);
+    match name {
+        Some(name) if keymap::set_keymap(name) => println!("Keyboard layout: {}", keymap::current().description),
+        Some(name) => println!("keymap: {}: unknown layout", name),
+        None => {
+            for layout in keymap::KEYMAPS.iter() {
+                let marker = if layout.name == keymap::current().name { "*" } else { " " };
+                print!("{} {:<7} {}\n", marker, layout.name, layout.description);
+            }
+            println!("");
+        }
+    }
+}
+
+fn ft_serial(input: &str) {
+    let arg = input[2..].split_whitespace().nth(1);
+    WRITER.lock().toggle_cmd(true);
+    if !serial::is_present() {
+        println!("serial: no serial port");
+        return;
+    }
+    match arg {
+        Some("on") => serial::set_mirroring(true),
+        Some("off") => serial::set_mirroring(false),
+        Some(arg) => {
+            println!("serial: {}: expected on or off", arg);
+            return;
+        }
+        None => {}
+    }
+    println!("COM1 at {} baud, console {}", serial::baud(), if serial::is_mirroring() { "mirrored" } else { "not mirrored" });
+}
+
+// kbdrate [delay rate], the rate may have a decimal like 10.9
+fn ft_kbdrate(input: &str) {
+    let mut args = input[2..].split_whitespace().skip(1);
+    let (delay, rate) = (args.next(), args.next());
     WRITER.lock().toggle_cmd(true);
-    println!("-----end of gdt at 0x838------");
+    let result = match (delay, rate) {
+        (None, _) => Ok(keyboard::typematic()),
+        (Some(delay), Some(rate)) => match (atousize(delay), atotenths(rate)) {
+            (Some(delay @ 250..=1000), Some(rate @ 20..=300)) => keyboard::set_typematic(delay as u32, rate as u32),
+            _ => {
+                println!("Please provide a delay between 250 and 1000 ms and a rate between 2 and 30 characters per second.");
+                return;
+            }
+        },
+        (Some(_), None) => {
+            println!("Please provide both the delay and the rate.");
+            return;
+        }
+    };
+    match result {
+        Ok((delay, rate)) => println!("Key repeat after {} ms, {}.{} characters per second", delay, rate / 10, rate % 10),
+        Err(KeyboardError::Busy) => println!("kbdrate: the keyboard is busy"),
+        Err(KeyboardError::NoResponse) => println!("kbdrate: the keyboard did not answer"),
+        Err(KeyboardError::Rejected) => println!("kbdrate: the keyboard rejected the command"),
+    }
+}
+
+// kill [-signal] pid, SIGTERM by default
+fn ft_kill(input: &str) {
+    let mut args = input[2..].split_whitespace().skip(1).peekable();
+    let signal = match args.peek().and_then(|arg| arg.strip_prefix('-')) {
+        Some(name) => {
+            args.next();
+            signal::from_name(name)
+        }
+        None => Some(signal::SIGTERM),
+    };
+    let pid = args.next().and_then(atousize);
+    WRITER.lock().toggle_cmd(true);
+    let (signal, pid) = match (signal, pid) {
+        (Some(signal), Some(pid)) => (signal, pid as Pid),
+        (None, _) => {
+            println!("kill: unknown signal");
+            return;
+        }
+        (_, None) => {
+            println!("Please provide the pid of the process to signal.");
+            return;
+        }
+    };
+    match signal::kill(pid, signal) {
+        Ok(()) => println!(""),
+        Err(KillError::NoSuchProcess) => println!("kill: ({}): no such process", pid),
+        Err(KillError::NotPermitted) => println!("kill: ({}): kernel threads cannot be signaled", pid),
+    }
 }
 
 fn ft_switch_tab(n: usize) {
@@ -437,17 +718,3 @@ fn ft_dump_stack(input: &str) {
         }
     }
 }
-
-fn atousize(s: &str) -> Option<usize> {
-    let mut result: usize = 0;
-    let chars = s.chars().peekable();
-
-    for c in chars {
-        if let Some(digit) = c.to_digit(10) {
-            result = result.checked_mul(10)?.checked_add(digit as usize)?;
-        } else {
-            return None;
-        }
-    }
-    Some(result)
-}
@@ -0,0 +1,710 @@
+extern crate spin;
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use self::spin::Mutex;
+
+use crate::idt::{self, without_interrupts, InterruptFrame};
+use crate::keymap;
+use crate::pic;
+use crate::mouse;
+use crate::ps2::{self, Port, Timeout, ACK, RESEND};
+use crate::signal;
+use crate::thread::{self, Interrupted, WaitQueue};
+
+const KEYBOARD_IRQ: u8 = 1;
+const QUEUE_SIZE: usize = 128;
+
+// set 1 prefixes
+const EXTENDED: u8 = 0xE0;
+const PAUSE: u8 = 0xE1;
+const RELEASED: u8 = 0x80;
+// E1 1D 45 E1 9D C5, Pause has no release code
+const PAUSE_SEQUENCE_LENGTH: u8 = 6;
+
+// commands, each byte of which the keyboard answers with ACK or RESEND
+const SET_LEDS: u8 = 0xED;
+const SET_TYPEMATIC: u8 = 0xF3;
+const MAX_RESENDS: usize = 3;
+// in ms, by the value of bits 5 and 6 of the typematic byte
+const TYPEMATIC_DELAYS: [u32; 4] = [250, 500, 750, 1000];
+// 500 ms and 10.9 characters per second, what a keyboard starts with
+const DEFAULT_TYPEMATIC: u8 = 0x2B;
+
+// keys by where they are on a US keyboard, the value is their set 1 make
+// code, with 0xE0 in the high byte for extended ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Key {
+    Escape = 0x01,
+    Digit1 = 0x02,
+    Digit2 = 0x03,
+    Digit3 = 0x04,
+    Digit4 = 0x05,
+    Digit5 = 0x06,
+    Digit6 = 0x07,
+    Digit7 = 0x08,
+    Digit8 = 0x09,
+    Digit9 = 0x0A,
+    Digit0 = 0x0B,
+    Minus = 0x0C,
+    Equals = 0x0D,
+    Backspace = 0x0E,
+    Tab = 0x0F,
+    Q = 0x10,
+    W = 0x11,
+    E = 0x12,
+    R = 0x13,
+    T = 0x14,
+    Y = 0x15,
+    U = 0x16,
+    I = 0x17,
+    O = 0x18,
+    P = 0x19,
+    LeftBracket = 0x1A,
+    RightBracket = 0x1B,
+    Enter = 0x1C,
+    LeftCtrl = 0x1D,
+    A = 0x1E,
+    S = 0x1F,
+    D = 0x20,
+    F = 0x21,
+    G = 0x22,
+    H = 0x23,
+    J = 0x24,
+    K = 0x25,
+    L = 0x26,
+    Semicolon = 0x27,
+    Quote = 0x28,
+    Backtick = 0x29,
+    LeftShift = 0x2A,
+    Backslash = 0x2B,
+    Z = 0x2C,
+    X = 0x2D,
+    C = 0x2E,
+    V = 0x2F,
+    B = 0x30,
+    N = 0x31,
+    M = 0x32,
+    Comma = 0x33,
+    Period = 0x34,
+    Slash = 0x35,
+    RightShift = 0x36,
+    KeypadStar = 0x37,
+    LeftAlt = 0x38,
+    Space = 0x39,
+    CapsLock = 0x3A,
+    F1 = 0x3B,
+    F2 = 0x3C,
+    F3 = 0x3D,
+    F4 = 0x3E,
+    F5 = 0x3F,
+    F6 = 0x40,
+    F7 = 0x41,
+    F8 = 0x42,
+    F9 = 0x43,
+    F10 = 0x44,
+    NumLock = 0x45,
+    ScrollLock = 0x46,
+    Keypad7 = 0x47,
+    Keypad8 = 0x48,
+    Keypad9 = 0x49,
+    KeypadMinus = 0x4A,
+    Keypad4 = 0x4B,
+    Keypad5 = 0x4C,
+    Keypad6 = 0x4D,
+    KeypadPlus = 0x4E,
+    Keypad1 = 0x4F,
+    Keypad2 = 0x50,
+    Keypad3 = 0x51,
+    Keypad0 = 0x52,
+    KeypadPeriod = 0x53,
+    // the extra key left of Z on ISO keyboards
+    NonUsBackslash = 0x56,
+    F11 = 0x57,
+    F12 = 0x58,
+    KeypadEnter = 0xE01C,
+    RightCtrl = 0xE01D,
+    KeypadSlash = 0xE035,
+    PrintScreen = 0xE037,
+    // AltGr on most non US keyboards
+    RightAlt = 0xE038,
+    Home = 0xE047,
+    Up = 0xE048,
+    PageUp = 0xE049,
+    Left = 0xE04B,
+    Right = 0xE04D,
+    End = 0xE04F,
+    Down = 0xE050,
+    PageDown = 0xE051,
+    Insert = 0xE052,
+    Delete = 0xE053,
+    LeftGui = 0xE05B,
+    RightGui = 0xE05C,
+    Menu = 0xE05D,
+    Pause = 0xE11D,
+}
+
+impl Key {
+    fn from_scancode(code: u8, extended: bool) -> Option<Key> {
+        use self::Key::*;
+        let key = if extended {
+            match code {
+                0x1C => KeypadEnter,
+                0x1D => RightCtrl,
+                0x35 => KeypadSlash,
+                0x37 => PrintScreen,
+                0x38 => RightAlt,
+                0x47 => Home,
+                0x48 => Up,
+                0x49 => PageUp,
+                0x4B => Left,
+                0x4D => Right,
+                0x4F => End,
+                0x50 => Down,
+                0x51 => PageDown,
+                0x52 => Insert,
+                0x53 => Delete,
+                0x5B => LeftGui,
+                0x5C => RightGui,
+                0x5D => Menu,
+                _ => return None,
+            }
+        } else {
+            match code {
+                0x01 => Escape,
+                0x02 => Digit1,
+                0x03 => Digit2,
+                0x04 => Digit3,
+                0x05 => Digit4,
+                0x06 => Digit5,
+                0x07 => Digit6,
+                0x08 => Digit7,
+                0x09 => Digit8,
+                0x0A => Digit9,
+                0x0B => Digit0,
+                0x0C => Minus,
+                0x0D => Equals,
+                0x0E => Backspace,
+                0x0F => Tab,
+                0x10 => Q,
+                0x11 => W,
+                0x12 => E,
+                0x13 => R,
+                0x14 => T,
+                0x15 => Y,
+                0x16 => U,
+                0x17 => I,
+                0x18 => O,
+                0x19 => P,
+                0x1A => LeftBracket,
+                0x1B => RightBracket,
+                0x1C => Enter,
+                0x1D => LeftCtrl,
+                0x1E => A,
+                0x1F => S,
+                0x20 => D,
+                0x21 => F,
+                0x22 => G,
+                0x23 => H,
+                0x24 => J,
+                0x25 => K,
+                0x26 => L,
+                0x27 => Semicolon,
+                0x28 => Quote,
+                0x29 => Backtick,
+                0x2A => LeftShift,
+                0x2B => Backslash,
+                0x2C => Z,
+                0x2D => X,
+                0x2E => C,
+                0x2F => V,
+                0x30 => B,
+                0x31 => N,
+                0x32 => M,
+                0x33 => Comma,
+                0x34 => Period,
+                0x35 => Slash,
+                0x36 => RightShift,
+                0x37 => KeypadStar,
+                0x38 => LeftAlt,
+                0x39 => Space,
+                0x3A => CapsLock,
+                0x3B => F1,
+                0x3C => F2,
+                0x3D => F3,
+                0x3E => F4,
+                0x3F => F5,
+                0x40 => F6,
+                0x41 => F7,
+                0x42 => F8,
+                0x43 => F9,
+                0x44 => F10,
+                0x45 => NumLock,
+                0x46 => ScrollLock,
+                0x47 => Keypad7,
+                0x48 => Keypad8,
+                0x49 => Keypad9,
+                0x4A => KeypadMinus,
+                0x4B => Keypad4,
+                0x4C => Keypad5,
+                0x4D => Keypad6,
+                0x4E => KeypadPlus,
+                0x4F => Keypad1,
+                0x50 => Keypad2,
+                0x51 => Keypad3,
+                0x52 => Keypad0,
+                0x53 => KeypadPeriod,
+                0x56 => NonUsBackslash,
+                0x57 => F11,
+                0x58 => F12,
+                _ => return None,
+            }
+        };
+        Some(key)
+    }
+
+    // what the keypad does with Num Lock off
+    fn without_num_lock(self) -> Key {
+        match self {
+            Key::Keypad7 => Key::Home,
+            Key::Keypad8 => Key::Up,
+            Key::Keypad9 => Key::PageUp,
+            Key::Keypad4 => Key::Left,
+            Key::Keypad6 => Key::Right,
+            Key::Keypad1 => Key::End,
+            Key::Keypad2 => Key::Down,
+            Key::Keypad3 => Key::PageDown,
+            Key::Keypad0 => Key::Insert,
+            Key::KeypadPeriod => Key::Delete,
+            key => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_ctrl: bool,
+    pub right_ctrl: bool,
+    pub alt: bool,
+    pub altgr: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl Modifiers {
+    pub const fn new() -> Modifiers {
+        Modifiers {
+            left_shift: false,
+            right_shift: false,
+            left_ctrl: false,
+            right_ctrl: false,
+            alt: false,
+            altgr: false,
+            caps_lock: false,
+            num_lock: false,
+            scroll_lock: false,
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.left_ctrl || self.right_ctrl
+    }
+
+    // the byte that goes with SET_LEDS
+    fn leds(&self) -> u8 {
+        (self.scroll_lock as u8) | (self.num_lock as u8) << 1 | (self.caps_lock as u8) << 2
+    }
+}
+
+// modifiers are as they were right after the key changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub pressed: bool,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    const NONE: KeyEvent = KeyEvent { key: Key::Escape, pressed: false, modifiers: Modifiers::new() };
+
+    // the set 1 code of the key, or'ed with 0x80 when released
+    pub fn scancode(&self) -> u16 {
+        match (self.key, self.pressed) {
+            (key, true) | (key @ Key::Pause, false) => key as u16,
+            (key, false) => key as u16 | RELEASED as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    Normal,
+    // after E0
+    Extended,
+    // bytes of the Pause sequence still to come
+    Pause(u8),
+}
+
+// turns set 1 scancodes into key events, keeping track of the modifiers
+pub struct KeyDecoder {
+    state: DecoderState,
+    modifiers: Modifiers,
+    // lock keys repeat while held, they only toggle on the first press
+    caps_lock_held: bool,
+    num_lock_held: bool,
+    scroll_lock_held: bool,
+}
+
+impl KeyDecoder {
+    pub const fn new() -> KeyDecoder {
+        KeyDecoder {
+            state: DecoderState::Normal,
+            modifiers: Modifiers::new(),
+            caps_lock_held: false,
+            num_lock_held: false,
+            scroll_lock_held: false,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.state {
+            DecoderState::Pause(remaining) => {
+                if remaining > 1 {
+                    self.state = DecoderState::Pause(remaining - 1);
+                    return None;
+                }
+                self.state = DecoderState::Normal;
+                Some(KeyEvent { key: Key::Pause, pressed: true, modifiers: self.modifiers })
+            }
+            DecoderState::Extended => {
+                self.state = DecoderState::Normal;
+                // E0 2A and E0 36 come with Print Screen and the navigation
+                // keys, as if to undo a shift the keyboard thinks is held
+                match byte & !RELEASED {
+                    0x2A | 0x36 => None,
+                    code => self.key_changed(code, true, byte & RELEASED == 0),
+                }
+            }
+            DecoderState::Normal => match byte {
+                EXTENDED => {
+                    self.state = DecoderState::Extended;
+                    None
+                }
+                PAUSE => {
+                    self.state = DecoderState::Pause(PAUSE_SEQUENCE_LENGTH - 1);
+                    None
+                }
+                _ => self.key_changed(byte & !RELEASED, false, byte & RELEASED == 0),
+            },
+        }
+    }
+
+    fn key_changed(&mut self, code: u8, extended: bool, pressed: bool) -> Option<KeyEvent> {
+        let mut key = Key::from_scancode(code, extended)?;
+        let modifiers = &mut self.modifiers;
+        match key {
+            Key::LeftShift => modifiers.left_shift = pressed,
+            Key::RightShift => modifiers.right_shift = pressed,
+            Key::LeftCtrl => modifiers.left_ctrl = pressed,
+            Key::RightCtrl => modifiers.right_ctrl = pressed,
+            Key::LeftAlt => modifiers.alt = pressed,
+            Key::RightAlt => modifiers.altgr = pressed,
+            Key::CapsLock => toggle(&mut modifiers.caps_lock, &mut self.caps_lock_held, pressed),
+            Key::NumLock => toggle(&mut modifiers.num_lock, &mut self.num_lock_held, pressed),
+            Key::ScrollLock => toggle(&mut modifiers.scroll_lock, &mut self.scroll_lock_held, pressed),
+            _ => {}
+        }
+        if !extended && !modifiers.num_lock {
+            key = key.without_num_lock();
+        }
+        Some(KeyEvent { key, pressed, modifiers: *modifiers })
+    }
+}
+
+fn toggle(lock: &mut bool, held: &mut bool, pressed: bool) {
+    if pressed && !*held {
+        *lock = !*lock;
+    }
+    *held = pressed;
+}
+
+// Single producer (the IRQ1 handler) / single consumer ring buffer.
+// One slot is always left empty to tell a full queue from an empty one.
+pub struct KeyQueue {
+    buffer: UnsafeCell<[KeyEvent; QUEUE_SIZE]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl Sync for KeyQueue {}
+
+impl KeyQueue {
+    pub const fn new() -> KeyQueue {
+        KeyQueue {
+            buffer: UnsafeCell::new([KeyEvent::NONE; QUEUE_SIZE]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn push(&self, event: KeyEvent) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % QUEUE_SIZE;
+        if next == self.tail.load(Ordering::Acquire) {
+            return false;
+        }
+        unsafe { (*self.buffer.get())[head] = event };
+        self.head.store(next, Ordering::Release);
+        true
+    }
+
+    pub fn pop(&self) -> Option<KeyEvent> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let event = unsafe { (*self.buffer.get())[tail] };
+        self.tail.store((tail + 1) % QUEUE_SIZE, Ordering::Release);
+        Some(event)
+    }
+}
+
+static EVENTS: KeyQueue = KeyQueue::new();
+static READERS: WaitQueue = WaitQueue::new();
+// only used from the interrupt handler, or with interrupts disabled
+static DECODER: Mutex<KeyDecoder> = Mutex::new(KeyDecoder::new());
+// set while a command is being sent, they don't nest
+static BUSY: AtomicBool = AtomicBool::new(false);
+// what the LEDs show and what the lock keys say they should, 0xFF until the first update
+static LEDS: AtomicU8 = AtomicU8::new(0xFF);
+static WANTED_LEDS: AtomicU8 = AtomicU8::new(0);
+// the LED thread, woken when the lock keys and the LEDs disagree
+static LED_UPDATES: WaitQueue = WaitQueue::new();
+static TYPEMATIC: AtomicU8 = AtomicU8::new(DEFAULT_TYPEMATIC);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardError {
+    Busy,
+    NoResponse,
+    // still asked to resend after MAX_RESENDS tries
+    Rejected,
+}
+
+impl From<Timeout> for KeyboardError {
+    fn from(_: Timeout) -> KeyboardError {
+        KeyboardError::NoResponse
+    }
+}
+
+fn keyboard_interrupt(_frame: &mut InterruptFrame) {
+    // the byte may already have been read while waiting for a command's ACK,
+    // and the mouse shares the output buffer
+    match ps2::poll_port() {
+        Some((Port::First, byte)) => handle_byte(byte),
+        Some((Port::Second, byte)) => mouse::handle_byte(byte),
+        None => {}
+    }
+}
+
+pub fn handle_byte(byte: u8) {
+    // decoded right away, so each event has the modifiers of when it happened
+    let event = match DECODER.lock().feed(byte) {
+        Some(event) => event,
+        None => return,
+    };
+    // sending SET_LEDS waits for the keyboard, not something to do in here
+    let leds = event.modifiers.leds();
+    if WANTED_LEDS.swap(leds, Ordering::Relaxed) != leds {
+        LED_UPDATES.wake_all();
+    }
+    // Ctrl+C goes to the program the shell waits for, not to whoever reads the keyboard
+    if keymap::is_ctrl_c(&event) && signal::interrupt_foreground() {
+        return;
+    }
+    // when the queue is full the key is dropped, like a real keyboard would
+    EVENTS.push(event);
+    READERS.wake_all();
+}
+
+// Runs f with the keyboard to ourselves, then brings the LEDs up to date.
+// A lock key toggled while a command is sent only changes WANTED_LEDS, the
+// command in progress updates the LEDs once it is done.
+fn with_keyboard<F: FnOnce() -> R, R>(f: F) -> Result<R, KeyboardError> {
+    without_interrupts(|| {
+        if BUSY.swap(true, Ordering::Acquire) {
+            return Err(KeyboardError::Busy);
+        }
+        let result = f();
+        loop {
+            let wanted = WANTED_LEDS.load(Ordering::Relaxed);
+            if LEDS.swap(wanted, Ordering::Relaxed) == wanted {
+                break;
+            }
+            // a keyboard that doesn't answer just keeps its LEDs as they are
+            let _ = send_command(SET_LEDS, wanted);
+        }
+        BUSY.store(false, Ordering::Release);
+        Ok(result)
+    })
+}
+
+fn update_leds() {
+    let _ = with_keyboard(|| ());
+}
+
+fn led_thread() {
+    loop {
+        // kernel threads don't get signals, nothing interrupts the wait
+        let _ = LED_UPDATES.wait_until(|| WANTED_LEDS.load(Ordering::Relaxed) != LEDS.load(Ordering::Relaxed));
+        update_leds();
+    }
+}
+
+fn send_command(command: u8, data: u8) -> Result<(), KeyboardError> {
+    send_byte(command)?;
+    send_byte(data)
+}
+
+fn send_byte(byte: u8) -> Result<(), KeyboardError> {
+    for _ in 0..=MAX_RESENDS {
+        ps2::write_data(Port::First, byte)?;
+        if wait_response()? == ACK {
+            return Ok(());
+        }
+    }
+    Err(KeyboardError::Rejected)
+}
+
+// ACK or RESEND, keys pressed in the meantime are handled as usual
+fn wait_response() -> Result<u8, KeyboardError> {
+    for _ in 0..ps2::TIMEOUT_POLLS {
+        match ps2::poll_port() {
+            Some((Port::First, ACK)) => return Ok(ACK),
+            Some((Port::First, RESEND)) => return Ok(RESEND),
+            Some((Port::First, byte)) => handle_byte(byte),
+            Some((Port::Second, byte)) => mouse::handle_byte(byte),
+            None => {}
+        }
+    }
+    Err(KeyboardError::NoResponse)
+}
+
+// in tenths of characters per second, the period is (8 + A) * 2^B * 4.17 ms
+// with A the low 3 bits and B the next 2
+fn typematic_rate(value: u8) -> u32 {
+    let period_us = (8 + (value & 0x07) as u32) * (1 << ((value >> 3) & 0x03)) * 4170;
+    (10_000_000 + period_us / 2) / period_us
+}
+
+// the delay in ms and the rate in tenths of characters per second
+fn decode_typematic(value: u8) -> (u32, u32) {
+    (TYPEMATIC_DELAYS[(value >> 5) as usize & 0x03], typematic_rate(value & 0x1F))
+}
+
+pub fn typematic() -> (u32, u32) {
+    decode_typematic(TYPEMATIC.load(Ordering::Relaxed))
+}
+
+// The keyboard only knows a few delays and rates, it gets the closest ones,
+// which are returned like typematic() does.
+pub fn set_typematic(delay_ms: u32, rate: u32) -> Result<(u32, u32), KeyboardError> {
+    let delay = (0..4u8).min_by_key(|&i| TYPEMATIC_DELAYS[i as usize].abs_diff(delay_ms)).unwrap();
+    let period = (0..32u8).min_by_key(|&value| typematic_rate(value).abs_diff(rate)).unwrap();
+    let value = delay << 5 | period;
+    with_keyboard(|| send_command(SET_TYPEMATIC, value))??;
+    TYPEMATIC.store(value, Ordering::Relaxed);
+    Ok(decode_typematic(value))
+}
+
+pub fn init_keyboard() {
+    while read_key().is_some() {}
+    // after the reset they are off, but the lock keys are too
+    if ps2::port_ready(Port::First) {
+        update_leds();
+    }
+    idt::register_irq_handler(KEYBOARD_IRQ, keyboard_interrupt);
+    pic::enable_irq(KEYBOARD_IRQ);
+}
+
+// needs the scheduler, until then the LEDs wait
+pub fn init_leds() {
+    if ps2::port_ready(Port::First) {
+        thread::spawn("leds", led_thread);
+    }
+}
+
+pub fn read_key() -> Option<KeyEvent> {
+    EVENTS.pop()
+}
+
+// sleeps until the keyboard interrupt gives us something to read
+pub fn wait_key() -> Result<KeyEvent, Interrupted> {
+    wait_key_or(|| false).map(|event| event.unwrap())
+}
+
+// Also wakes up, with None, once `ready` says another input has something.
+// That input has to call wake_readers when it does.
+pub fn wait_key_or<F: FnMut() -> bool>(mut ready: F) -> Result<Option<KeyEvent>, Interrupted> {
+    let mut event = None;
+    READERS.wait_until(|| {
+        event = EVENTS.pop();
+        event.is_some() || ready()
+    })?;
+    Ok(event)
+}
+
+pub fn wake_readers() {
+    READERS.wake_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: Key) -> KeyEvent {
+        KeyEvent { key, pressed: true, modifiers: Modifiers::new() }
+    }
+
+    #[test_case]
+    fn queue_is_first_in_first_out() {
+        let queue = KeyQueue::new();
+        assert_eq!(queue.pop(), None);
+        assert!(queue.push(press(Key::A)));
+        assert!(queue.push(press(Key::B)));
+        assert_eq!(queue.pop(), Some(press(Key::A)));
+        assert_eq!(queue.pop(), Some(press(Key::B)));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test_case]
+    fn queue_drops_keys_when_full() {
+        let queue = KeyQueue::new();
+        for _ in 0..QUEUE_SIZE - 1 {
+            assert!(queue.push(press(Key::A)));
+        }
+        assert!(!queue.push(press(Key::B)));
+        // a slot frees up after a pop
+        assert_eq!(queue.pop(), Some(press(Key::A)));
+        assert!(queue.push(press(Key::B)));
+    }
+
+    #[test_case]
+    fn queue_wraps_around() {
+        let queue = KeyQueue::new();
+        for round in 0..3 * QUEUE_SIZE {
+            let key = if round % 2 == 0 { Key::A } else { Key::B };
+            assert!(queue.push(press(key)));
+            assert_eq!(queue.pop(), Some(press(key)));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+}
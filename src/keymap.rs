@@ -0,0 +1,238 @@
+extern crate spin;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use self::spin::Mutex;
+
+use crate::idt::without_interrupts;
+use crate::keyboard::{Key, KeyEvent};
+use crate::multiboot;
+
+// Keyboard layouts, as data. Each plane is what the keys of LAYOUT_KEYS type,
+// in that order, '\0' being nothing. Keys that type the same thing whatever
+// the layout (Enter, the keypad...) are in fixed_char.
+
+const BASE: usize = 0;
+const SHIFT: usize = 1;
+const ALTGR: usize = 2;
+
+const LAYOUT_KEYS: [Key; 48] = [
+    Key::Backtick, Key::Digit1, Key::Digit2, Key::Digit3, Key::Digit4, Key::Digit5, Key::Digit6,
+    Key::Digit7, Key::Digit8, Key::Digit9, Key::Digit0, Key::Minus, Key::Equals,
+    Key::Q, Key::W, Key::E, Key::R, Key::T, Key::Y, Key::U, Key::I, Key::O, Key::P,
+    Key::LeftBracket, Key::RightBracket, Key::Backslash,
+    Key::A, Key::S, Key::D, Key::F, Key::G, Key::H, Key::J, Key::K, Key::L, Key::Semicolon, Key::Quote,
+    Key::NonUsBackslash, Key::Z, Key::X, Key::C, Key::V, Key::B, Key::N, Key::M,
+    Key::Comma, Key::Period, Key::Slash,
+];
+
+pub struct Keymap {
+    pub name: &'static str,
+    pub description: &'static str,
+    planes: [&'static str; 3],
+    // keys that only put an accent on the next one, by plane
+    dead_keys: &'static [(Key, usize)],
+}
+
+pub const KEYMAPS: [Keymap; 4] = [
+    Keymap {
+        name: "us",
+        description: "US QWERTY",
+        planes: [
+            "`1234567890-=qwertyuiop[]\\asdfghjkl;'\\zxcvbnm,./",
+            "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\"|ZXCVBNM<>?",
+            "",
+        ],
+        dead_keys: &[],
+    },
+    Keymap {
+        name: "fr",
+        description: "French AZERTY",
+        planes: [
+            "²&é\"'(-è_çà)=azertyuiop^$*qsdfghjklmù<wxcvbn,;:!",
+            "\x001234567890°+AZERTYUIOP¨£µQSDFGHJKLM%>WXCVBN?./§",
+            "\0\0~#{[|`\\^@]}\0\0€\0\0\0\0\0\0\0\0¤\0",
+        ],
+        dead_keys: &[(Key::LeftBracket, BASE), (Key::LeftBracket, SHIFT)],
+    },
+    Keymap {
+        name: "de",
+        description: "German QWERTZ",
+        planes: [
+            "^1234567890ß´qwertzuiopü+#asdfghjklöä<yxcvbnm,.-",
+            "°!\"§$%&/()=?`QWERTZUIOPÜ*'ASDFGHJKLÖÄ>YXCVBNM;:_",
+            "\0\0²³\0\0\0{[]}\\\0@\0€\0\0\0\0\0\0\0\0~\0\0\0\0\0\0\0\0\0\0\0\0|\0\0\0\0\0\0µ",
+        ],
+        dead_keys: &[(Key::Backtick, BASE), (Key::Equals, BASE), (Key::Equals, SHIFT)],
+    },
+    Keymap {
+        name: "dvorak",
+        description: "US Dvorak",
+        planes: [
+            "`1234567890[]',.pyfgcrl/=\\aoeuidhtns-\\;qjkxbmwvz",
+            "~!@#$%^&*(){}\"<>PYFGCRL?+|AOEUIDHTNS_|:QJKXBMWVZ",
+            "",
+        ],
+        dead_keys: &[],
+    },
+];
+
+// what a dead key does to the next character
+const COMPOSE: [(char, char, char); 40] = [
+    ('^', 'a', 'â'), ('^', 'e', 'ê'), ('^', 'i', 'î'), ('^', 'o', 'ô'), ('^', 'u', 'û'),
+    ('^', 'A', 'Â'), ('^', 'E', 'Ê'), ('^', 'I', 'Î'), ('^', 'O', 'Ô'), ('^', 'U', 'Û'),
+    ('¨', 'a', 'ä'), ('¨', 'e', 'ë'), ('¨', 'i', 'ï'), ('¨', 'o', 'ö'), ('¨', 'u', 'ü'),
+    ('¨', 'y', 'ÿ'), ('¨', 'A', 'Ä'), ('¨', 'E', 'Ë'), ('¨', 'O', 'Ö'), ('¨', 'U', 'Ü'),
+    ('´', 'a', 'á'), ('´', 'e', 'é'), ('´', 'i', 'í'), ('´', 'o', 'ó'), ('´', 'u', 'ú'),
+    ('´', 'y', 'ý'), ('´', 'E', 'É'), ('´', 'A', 'Á'), ('´', 'O', 'Ó'), ('´', 'U', 'Ú'),
+    ('`', 'a', 'à'), ('`', 'e', 'è'), ('`', 'i', 'ì'), ('`', 'o', 'ò'), ('`', 'u', 'ù'),
+    ('`', 'A', 'À'), ('`', 'E', 'È'), ('`', 'I', 'Ì'), ('`', 'O', 'Ò'), ('`', 'U', 'Ù'),
+];
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+// the accent of the last dead key, waiting for the next character
+static PENDING_ACCENT: Mutex<Option<char>> = Mutex::new(None);
+
+pub fn current() -> &'static Keymap {
+    &KEYMAPS[CURRENT.load(Ordering::Relaxed)]
+}
+
+// false if there is no such layout
+pub fn set_keymap(name: &str) -> bool {
+    match KEYMAPS.iter().position(|keymap| keymap.name == name) {
+        Some(index) => {
+            CURRENT.store(index, Ordering::Relaxed);
+            without_interrupts(|| *PENDING_ACCENT.lock() = None);
+            true
+        }
+        None => false,
+    }
+}
+
+// `keymap=fr` on the kernel command line
+pub fn init_keymap() {
+    let command_line = match multiboot::boot_info().and_then(|info| info.command_line()) {
+        Some(command_line) => command_line,
+        None => return,
+    };
+    for name in command_line.split_whitespace().filter_map(|arg| arg.strip_prefix("keymap=")) {
+        if !set_keymap(name) {
+            crate::println!("Unknown keymap {}, keeping {}", name, current().name);
+        }
+    }
+}
+
+impl Keymap {
+    fn lookup(&self, key: Key, plane: usize) -> Option<char> {
+        let index = LAYOUT_KEYS.iter().position(|&other| other == key)?;
+        match self.planes[plane].chars().nth(index) {
+            Some('\0') | None => None,
+            c => c,
+        }
+    }
+
+    // the plane and character a key types, None for keys that type nothing
+    fn resolve(&self, event: &KeyEvent) -> Option<(usize, char)> {
+        let modifiers = &event.modifiers;
+        if modifiers.altgr {
+            return self.lookup(event.key, ALTGR).map(|c| (ALTGR, c));
+        }
+        let base = self.lookup(event.key, BASE)?;
+        let shifted = self.lookup(event.key, SHIFT);
+        // Caps Lock only changes letters, é stays é on the French digit row
+        let letter = base.is_alphabetic() && shifted.is_some_and(|c| c.is_uppercase());
+        let shift = modifiers.shift() != (letter && modifiers.caps_lock);
+        match (shift, shifted) {
+            (true, Some(c)) => Some((SHIFT, c)),
+            (true, None) => None,
+            (false, _) => Some((BASE, base)),
+        }
+    }
+
+    fn is_dead(&self, key: Key, plane: usize) -> bool {
+        self.dead_keys.contains(&(key, plane))
+    }
+}
+
+// keys that are the same on every layout
+fn fixed_char(key: Key) -> Option<char> {
+    let c = match key {
+        Key::Escape => '\x1b',
+        Key::Backspace => '\x08',
+        Key::Tab => '\t',
+        Key::Enter | Key::KeypadEnter => '\n',
+        Key::Space => ' ',
+        Key::KeypadSlash => '/',
+        Key::KeypadStar => '*',
+        Key::KeypadMinus => '-',
+        Key::KeypadPlus => '+',
+        Key::KeypadPeriod => '.',
+        Key::Keypad0 => '0',
+        Key::Keypad1 => '1',
+        Key::Keypad2 => '2',
+        Key::Keypad3 => '3',
+        Key::Keypad4 => '4',
+        Key::Keypad5 => '5',
+        Key::Keypad6 => '6',
+        Key::Keypad7 => '7',
+        Key::Keypad8 => '8',
+        Key::Keypad9 => '9',
+        _ => return None,
+    };
+    Some(c)
+}
+
+// the character a key types without modifiers, to match shortcuts like
+// Ctrl+C on what the key says rather than where it is
+pub fn base_char(key: Key) -> Option<char> {
+    current().lookup(key, BASE).or_else(|| fixed_char(key))
+}
+
+pub fn is_ctrl_c(event: &KeyEvent) -> bool {
+    event.pressed && event.modifiers.ctrl() && base_char(event.key) == Some('c')
+}
+
+// what a key press types with the current layout: nothing, a character,
+// or, after a dead key that does not go with it, the accent and the character
+pub fn type_key(event: &KeyEvent) -> impl Iterator<Item = char> {
+    let typed = if event.pressed { translate(event) } else { (None, None) };
+    typed.0.into_iter().chain(typed.1)
+}
+
+fn translate(event: &KeyEvent) -> (Option<char>, Option<char>) {
+    let keymap = current();
+    let (plane, c) = match keymap.resolve(event) {
+        Some(resolved) => resolved,
+        None => match fixed_char(event.key) {
+            Some(c) => (BASE, c),
+            // modifiers and the like don't cancel the accent
+            None => return (None, None),
+        },
+    };
+    without_interrupts(|| {
+        let mut pending = PENDING_ACCENT.lock();
+        if keymap.is_dead(event.key, plane) {
+            // twice the same dead key types the accent itself
+            return match pending.take() {
+                Some(accent) if accent == c => (Some(c), None),
+                Some(accent) => {
+                    *pending = Some(c);
+                    (Some(accent), None)
+                }
+                None => {
+                    *pending = Some(c);
+                    (None, None)
+                }
+            };
+        }
+        match pending.take() {
+            None => (Some(c), None),
+            // the accent alone
+            Some(accent) if c == ' ' => (Some(accent), None),
+            Some(accent) => match COMPOSE.iter().find(|&&(a, base, _)| a == accent && base == c) {
+                Some(&(_, _, composed)) => (Some(composed), None),
+                None => (Some(accent), Some(c)),
+            },
+        }
+    })
+}
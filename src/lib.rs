@@ -1,28 +1,103 @@
 #![feature(naked_functions)]
+#![feature(alloc_error_handler)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+extern crate kfs_core;
+
 mod vga_buffer;
 mod gdt;
+mod idt;
+mod pic;
+mod serial;
+mod ps2;
 mod io;
+mod keyboard;
+mod keymap;
+mod mouse;
+mod timer;
+mod multiboot;
+mod pmm;
+mod paging;
+mod heap;
+mod elf;
+mod usermode;
+mod syscall;
+mod process;
+mod signal;
+mod scheduler;
+mod thread;
 mod tetris;
+#[cfg(not(test))]
+mod panic_screen;
+#[cfg(test)]
+mod testing;
 
 use core::panic::PanicInfo;
 
 #[no_mangle]
-pub extern fn k_main() {
-    vga_buffer::print_welcome_screen();
+pub extern fn k_main(multiboot_info: u32) {
     gdt::init_gdt();
+    idt::init_idt();
+    multiboot::init_multiboot(multiboot_info);
+    serial::init_serial();
+    if let Some(boot_info) = multiboot::boot_info() {
+        pmm::init_pmm(&boot_info);
+        paging::init_paging(&boot_info);
+        heap::init_heap();
+    }
+    gdt::init_tss();
+    // the tests only need memory and the serial line, the rest stays off
+    #[cfg(test)]
+    test_main();
+    vga_buffer::print_welcome_screen();
+    pic::init_pic();
+    ps2::init_ps2();
+    keyboard::init_keyboard();
+    keymap::init_keymap();
+    timer::init_timer(timer::DEFAULT_FREQUENCY);
+    scheduler::init_scheduler();
+    keyboard::init_leds();
+    mouse::init_mouse();
+    thread::spawn("shell", shell);
+    idt::enable_interrupts();
+    // from now on k_main is the idle process, it only runs when nobody else can.
+    // Whatever an interrupt woke up gets the CPU right away.
+    loop {
+        idt::enable_interrupts_and_hlt();
+        thread::yield_now();
+    }
+}
+
+fn shell() {
     loop{
-        let scan_code = read_data();
-        handle_keyboard_input(scan_code);
+        // kernel threads don't get signals, nothing interrupts the wait
+        match keyboard::wait_key_or(serial::has_input) {
+            Ok(Some(event)) => handle_keyboard_input(event),
+            Ok(None) => {
+                while let Some(byte) = serial::read_byte() {
+                    handle_serial_input(byte);
+                }
+            }
+            Err(_) => {}
+        }
     }
 }
 
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    panic_screen::kernel_panic(info)
+}
+
+#[cfg(test)]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    loop {}
+    testing::test_panic_handler(info)
 }
 
 fn print_mem_area(addr: *mut i32, size: usize) {
@@ -77,4 +152,4 @@ fn print_mem_line(addr: *mut i32) {
     }
 }
 
-use io::{handle_keyboard_input, read_data};
\ No newline at end of file
+use io::{handle_keyboard_input, handle_serial_input};
\ No newline at end of file
@@ -0,0 +1,203 @@
+extern crate spin;
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use self::spin::Mutex;
+
+use crate::idt::{self, InterruptFrame};
+use crate::keyboard;
+use crate::pic;
+use crate::ps2::{self, Port, Timeout, ACK};
+use crate::thread::{self, WaitQueue};
+use crate::vga_buffer::{BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+use println;
+
+const MOUSE_IRQ: u8 = 12;
+
+// mouse commands, each one answered with ACK
+const GET_ID: u8 = 0xF2;
+const SET_SAMPLE_RATE: u8 = 0xF3;
+const ENABLE_REPORTING: u8 = 0xF4;
+// setting these sample rates in a row is how a wheel mouse is told to use
+// its wheel, it then says so with a different id
+const WHEEL_SAMPLE_RATES: [u8; 3] = [200, 100, 80];
+const WHEEL_ID: u8 = 3;
+
+// first byte of a packet
+const ALWAYS_ONE: u8 = 0x08;
+const X_SIGN: u8 = 0x10;
+const Y_SIGN: u8 = 0x20;
+const X_OVERFLOW: u8 = 0x40;
+const Y_OVERFLOW: u8 = 0x80;
+
+// mouse counts per character, which are about twice as tall as they are wide
+const COUNTS_PER_COLUMN: i32 = 8;
+const COUNTS_PER_ROW: i32 = 16;
+const LINES_PER_NOTCH: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseError {
+    NoResponse,
+    // a byte that is not the ACK we waited for
+    Unexpected(u8),
+}
+
+impl From<Timeout> for MouseError {
+    fn from(_: Timeout) -> MouseError {
+        MouseError::NoResponse
+    }
+}
+
+// up is positive for dy, and away from the user negative for the wheel
+struct MouseEvent {
+    dx: i32,
+    dy: i32,
+    wheel: i32,
+}
+
+// 3 byte packets, 4 with the wheel
+pub struct MouseDecoder {
+    packet: [u8; 4],
+    received: usize,
+    packet_size: usize,
+}
+
+impl MouseDecoder {
+    pub const fn new() -> MouseDecoder {
+        MouseDecoder { packet: [0; 4], received: 0, packet_size: 3 }
+    }
+
+    fn feed(&mut self, byte: u8) -> Option<MouseEvent> {
+        // a lost byte shifts everything, wait for one that can start a packet
+        if self.received == 0 && byte & ALWAYS_ONE == 0 {
+            return None;
+        }
+        self.packet[self.received] = byte;
+        self.received += 1;
+        if self.received < self.packet_size {
+            return None;
+        }
+        self.received = 0;
+        let flags = self.packet[0];
+        // the mouse moved more than it could tell, better not move at all
+        if flags & (X_OVERFLOW | Y_OVERFLOW) != 0 {
+            return None;
+        }
+        let dx = self.packet[1] as i32 - if flags & X_SIGN != 0 { 256 } else { 0 };
+        let dy = self.packet[2] as i32 - if flags & Y_SIGN != 0 { 256 } else { 0 };
+        // the low 4 bits, signed
+        let wheel = if self.packet_size == 4 { ((self.packet[3] << 4) as i8 >> 4) as i32 } else { 0 };
+        Some(MouseEvent { dx, dy, wheel })
+    }
+}
+
+// only used from the interrupt handler, or with interrupts disabled
+static DECODER: Mutex<MouseDecoder> = Mutex::new(MouseDecoder::new());
+// what the pointer thread has yet to do
+static MOTION_X: AtomicI32 = AtomicI32::new(0);
+static MOTION_Y: AtomicI32 = AtomicI32::new(0);
+static WHEEL: AtomicI32 = AtomicI32::new(0);
+static POINTER: WaitQueue = WaitQueue::new();
+
+fn mouse_interrupt(_frame: &mut InterruptFrame) {
+    // the keyboard shares the output buffer
+    match ps2::poll_port() {
+        Some((Port::Second, byte)) => handle_byte(byte),
+        Some((Port::First, byte)) => keyboard::handle_byte(byte),
+        None => {}
+    }
+}
+
+pub fn handle_byte(byte: u8) {
+    let event = match DECODER.lock().feed(byte) {
+        Some(event) => event,
+        None => return,
+    };
+    MOTION_X.fetch_add(event.dx, Ordering::Relaxed);
+    MOTION_Y.fetch_add(event.dy, Ordering::Relaxed);
+    WHEEL.fetch_add(event.wheel, Ordering::Relaxed);
+    POINTER.wake_all();
+}
+
+// The screen can't be touched from the interrupt handler, whoever it
+// interrupted may be printing. The motion piles up until this thread runs.
+fn pointer_thread() {
+    let width = BUFFER_WIDTH as i32 * COUNTS_PER_COLUMN;
+    let height = BUFFER_HEIGHT as i32 * COUNTS_PER_ROW;
+    // in mouse counts from the top left corner
+    let (mut x, mut y) = (width / 2, height / 2);
+    loop {
+        // kernel threads don't get signals, nothing interrupts the wait
+        let _ = POINTER.wait_until(|| {
+            MOTION_X.load(Ordering::Relaxed) != 0 || MOTION_Y.load(Ordering::Relaxed) != 0 || WHEEL.load(Ordering::Relaxed) != 0
+        });
+        x = (x + MOTION_X.swap(0, Ordering::Relaxed)).clamp(0, width - 1);
+        y = (y - MOTION_Y.swap(0, Ordering::Relaxed)).clamp(0, height - 1);
+        let wheel = WHEEL.swap(0, Ordering::Relaxed);
+        let mut writer = WRITER.lock();
+        for _ in 0..wheel.abs() * LINES_PER_NOTCH {
+            if wheel < 0 {
+                writer.scroll_up();
+            } else {
+                writer.scroll_down();
+            }
+        }
+        writer.move_pointer((y / COUNTS_PER_ROW) as usize, (x / COUNTS_PER_COLUMN) as usize);
+    }
+}
+
+// keys pressed in the meantime are handled as usual
+fn read_response() -> Result<u8, MouseError> {
+    for _ in 0..ps2::TIMEOUT_POLLS {
+        match ps2::poll_port() {
+            Some((Port::Second, byte)) => return Ok(byte),
+            Some((Port::First, byte)) => keyboard::handle_byte(byte),
+            None => {}
+        }
+    }
+    Err(MouseError::NoResponse)
+}
+
+fn send_command(command: u8) -> Result<(), MouseError> {
+    ps2::write_data(Port::Second, command)?;
+    match read_response()? {
+        ACK => Ok(()),
+        byte => Err(MouseError::Unexpected(byte)),
+    }
+}
+
+// true if the mouse has a wheel, and now uses it
+fn setup() -> Result<bool, MouseError> {
+    for &rate in WHEEL_SAMPLE_RATES.iter() {
+        send_command(SET_SAMPLE_RATE)?;
+        send_command(rate)?;
+    }
+    send_command(GET_ID)?;
+    let wheel = read_response()? == WHEEL_ID;
+    send_command(ENABLE_REPORTING)?;
+    Ok(wheel)
+}
+
+// needs the scheduler, for the pointer thread
+pub fn init_mouse() {
+    if !ps2::port_ready(Port::Second) {
+        return;
+    }
+    match setup() {
+        Ok(wheel) => {
+            DECODER.lock().packet_size = if wheel { 4 } else { 3 };
+            println!("PS/2 mouse: ok{}", if wheel { ", with a wheel" } else { "" });
+        }
+        Err(MouseError::NoResponse) => {
+            println!("PS/2 mouse: not responding");
+            return;
+        }
+        Err(MouseError::Unexpected(byte)) => {
+            println!("PS/2 mouse: unexpected answer ({:#04x})", byte);
+            return;
+        }
+    }
+    idt::register_irq_handler(MOUSE_IRQ, mouse_interrupt);
+    pic::enable_irq(MOUSE_IRQ);
+    thread::spawn("mouse", pointer_thread);
+}
@@ -0,0 +1,318 @@
+use core::ptr::read_unaligned;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::paging::{direct_map_end, phys_to_virt, virt_to_phys};
+
+// Multiboot2 information structure, see
+// https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html
+
+const TAG_END: u32 = 0;
+const TAG_COMMAND_LINE: u32 = 1;
+const TAG_BOOT_LOADER_NAME: u32 = 2;
+const TAG_MODULE: u32 = 3;
+const TAG_BASIC_MEMORY_INFO: u32 = 4;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_ELF_SECTIONS: u32 = 9;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+
+static BOOT_INFO_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+pub fn init_multiboot(addr: u32) {
+    BOOT_INFO_ADDR.store(addr as usize, Ordering::Relaxed);
+}
+
+// GRUB gives us a physical address, we read it through the kernel mapping
+pub fn boot_info() -> Option<BootInfo> {
+    match BOOT_INFO_ADDR.load(Ordering::Relaxed) {
+        0 => None,
+        addr => Some(BootInfo { addr: phys_to_virt(addr) }),
+    }
+}
+
+unsafe fn read_u32(addr: usize) -> u32 {
+    read_unaligned(addr as *const u32)
+}
+
+unsafe fn read_u64(addr: usize) -> u64 {
+    read_unaligned(addr as *const u64)
+}
+
+// strings handed over by GRUB are NUL terminated and live as long as the kernel
+unsafe fn read_str(addr: usize, max_len: usize) -> &'static str {
+    let bytes = core::slice::from_raw_parts(addr as *const u8, max_len);
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(max_len);
+    core::str::from_utf8(&bytes[..len]).unwrap_or("<invalid utf-8>")
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BootInfo {
+    addr: usize,
+}
+
+impl BootInfo {
+    // physical bounds of the structure
+    pub fn start_address(&self) -> usize {
+        virt_to_phys(self.addr)
+    }
+
+    pub fn total_size(&self) -> usize {
+        unsafe { read_u32(self.addr) as usize }
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.start_address() + self.total_size()
+    }
+
+    pub fn tags(&self) -> TagIter {
+        TagIter {
+            current: self.addr + 8,
+            end: self.addr + self.total_size(),
+        }
+    }
+
+    fn find_tag(&self, typ: u32) -> Option<Tag> {
+        self.tags().find(|tag| tag.typ == typ)
+    }
+
+    pub fn command_line(&self) -> Option<&'static str> {
+        self.find_tag(TAG_COMMAND_LINE).map(|tag| tag.string(8))
+    }
+
+    pub fn boot_loader_name(&self) -> Option<&'static str> {
+        self.find_tag(TAG_BOOT_LOADER_NAME).map(|tag| tag.string(8))
+    }
+
+    // lower and upper memory sizes, in KiB
+    pub fn basic_memory_info(&self) -> Option<(u32, u32)> {
+        self.find_tag(TAG_BASIC_MEMORY_INFO).map(|tag| unsafe {
+            (read_u32(tag.addr + 8), read_u32(tag.addr + 12))
+        })
+    }
+
+    pub fn memory_map(&self) -> Option<MemoryMapIter> {
+        self.find_tag(TAG_MEMORY_MAP).map(|tag| {
+            let entry_size = unsafe { read_u32(tag.addr + 8) } as usize;
+            MemoryMapIter {
+                current: tag.addr + 16,
+                end: tag.addr + tag.size,
+                entry_size,
+            }
+        })
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = Module> {
+        self.tags().filter(|tag| tag.typ == TAG_MODULE).map(|tag| unsafe {
+            Module {
+                start: read_u32(tag.addr + 8),
+                end: read_u32(tag.addr + 12),
+                cmdline: tag.string(16),
+            }
+        })
+    }
+
+    pub fn framebuffer(&self) -> Option<FramebufferInfo> {
+        self.find_tag(TAG_FRAMEBUFFER).map(|tag| unsafe {
+            FramebufferInfo {
+                addr: read_u64(tag.addr + 8),
+                pitch: read_u32(tag.addr + 16),
+                width: read_u32(tag.addr + 20),
+                height: read_u32(tag.addr + 24),
+                bpp: *((tag.addr + 28) as *const u8),
+                typ: *((tag.addr + 29) as *const u8),
+            }
+        })
+    }
+
+    pub fn elf_sections(&self) -> Option<ElfSectionIter> {
+        self.find_tag(TAG_ELF_SECTIONS).map(|tag| unsafe {
+            let num = read_u32(tag.addr + 8) as usize;
+            let entry_size = read_u32(tag.addr + 12) as usize;
+            let string_index = read_u32(tag.addr + 16) as usize;
+            let first = tag.addr + 20;
+            // GRUB loads the string table itself and gives its physical address
+            let mut string_table = 0;
+            if string_index < num {
+                let phys = read_u32(first + string_index * entry_size + 12) as usize;
+                if phys != 0 && phys < direct_map_end() {
+                    string_table = phys_to_virt(phys);
+                }
+            }
+            ElfSectionIter {
+                current: first,
+                remaining: num,
+                entry_size,
+                string_table,
+            }
+        })
+    }
+
+    // the newest RSDP wins when GRUB hands over both
+    pub fn rsdp(&self) -> Option<Rsdp> {
+        let tag = self.find_tag(TAG_ACPI_NEW).or_else(|| self.find_tag(TAG_ACPI_OLD))?;
+        let rsdp = tag.addr + 8;
+        unsafe {
+            let revision = *((rsdp + 15) as *const u8);
+            Some(Rsdp {
+                oem_id: read_str(rsdp + 9, 6),
+                revision,
+                rsdt_address: read_u32(rsdp + 16),
+                xsdt_address: if revision >= 2 && tag.typ == TAG_ACPI_NEW {
+                    Some(read_u64(rsdp + 24))
+                } else {
+                    None
+                },
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tag {
+    pub typ: u32,
+    pub size: usize,
+    addr: usize,
+}
+
+impl Tag {
+    fn string(&self, offset: usize) -> &'static str {
+        unsafe { read_str(self.addr + offset, self.size.saturating_sub(offset)) }
+    }
+}
+
+pub struct TagIter {
+    current: usize,
+    end: usize,
+}
+
+impl Iterator for TagIter {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        if self.current + 8 > self.end {
+            return None;
+        }
+        let (typ, size) = unsafe { (read_u32(self.current), read_u32(self.current + 4) as usize) };
+        if typ == TAG_END || size < 8 {
+            return None;
+        }
+        let tag = Tag { typ, size, addr: self.current };
+        // tags are padded to 8 bytes
+        self.current += (size + 7) & !7;
+        Some(tag)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAreaType {
+    Available,
+    Reserved,
+    AcpiReclaimable,
+    AcpiNvs,
+    Defective,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryArea {
+    pub base_addr: u64,
+    pub length: u64,
+    pub typ: MemoryAreaType,
+}
+
+pub struct MemoryMapIter {
+    current: usize,
+    end: usize,
+    entry_size: usize,
+}
+
+impl Iterator for MemoryMapIter {
+    type Item = MemoryArea;
+
+    fn next(&mut self) -> Option<MemoryArea> {
+        if self.entry_size == 0 || self.current + self.entry_size > self.end {
+            return None;
+        }
+        let area = unsafe {
+            MemoryArea {
+                base_addr: read_u64(self.current),
+                length: read_u64(self.current + 8),
+                typ: match read_u32(self.current + 16) {
+                    1 => MemoryAreaType::Available,
+                    3 => MemoryAreaType::AcpiReclaimable,
+                    4 => MemoryAreaType::AcpiNvs,
+                    5 => MemoryAreaType::Defective,
+                    _ => MemoryAreaType::Reserved,
+                },
+            }
+        };
+        self.current += self.entry_size;
+        Some(area)
+    }
+}
+
+// start and end are physical addresses
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    pub start: u32,
+    pub end: u32,
+    pub cmdline: &'static str,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    pub addr: u64,
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+    pub typ: u8, // 0 indexed, 1 RGB, 2 EGA text
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSection {
+    pub name: &'static str,
+    pub typ: u32,
+    pub flags: u32,
+    pub addr: u32,
+    pub size: u32,
+}
+
+pub struct ElfSectionIter {
+    current: usize,
+    remaining: usize,
+    entry_size: usize,
+    string_table: usize,
+}
+
+impl Iterator for ElfSectionIter {
+    type Item = ElfSection;
+
+    fn next(&mut self) -> Option<ElfSection> {
+        if self.remaining == 0 {
+            return None;
+        }
+        // Elf32_Shdr: name, type, flags, addr, offset, size, ...
+        let section = unsafe {
+            let name_offset = read_u32(self.current) as usize;
+            ElfSection {
+                name: if self.string_table != 0 { read_str(self.string_table + name_offset, 64) } else { "" },
+                typ: read_u32(self.current + 4),
+                flags: read_u32(self.current + 8),
+                addr: read_u32(self.current + 12),
+                size: read_u32(self.current + 20),
+            }
+        };
+        self.current += self.entry_size;
+        self.remaining -= 1;
+        Some(section)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub oem_id: &'static str,
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub xsdt_address: Option<u64>,
+}
@@ -0,0 +1,461 @@
+extern crate spin;
+
+use core::arch::asm;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use alloc::collections::BTreeMap;
+
+use self::spin::Mutex;
+
+use crate::idt::{self, InterruptFrame};
+use crate::multiboot::BootInfo;
+use crate::pmm;
+use crate::println;
+
+pub const PAGE_SIZE: usize = 4096;
+const ENTRIES: usize = 1024;
+
+// the kernel lives in the last GiB of every address space, with the start of
+// physical memory mapped right at KERNEL_BASE
+pub const KERNEL_BASE: usize = 0xC000_0000;
+const KERNEL_DIRECTORY_INDEX: usize = KERNEL_BASE >> 22;
+// what the boot trampoline (boot.asm) maps before init_paging runs
+const BOOT_MAPPED_END: usize = 16 * 1024 * 1024;
+
+// page directory and page table entry flags
+pub const PRESENT: u32 = 1 << 0;
+pub const WRITABLE: u32 = 1 << 1;
+pub const USER: u32 = 1 << 2;
+// one of the bits left to the OS: a read-only page shared by fork, copied on the first write
+const COPY_ON_WRITE: u32 = 1 << 9;
+const FLAGS_MASK: u32 = 0xFFF;
+
+// the last directory entry points to the directory itself, which makes every
+// page table reachable at RECURSIVE_TABLES + index * PAGE_SIZE once paging is on
+const RECURSIVE_INDEX: usize = ENTRIES - 1;
+const RECURSIVE_TABLES: usize = 0xFFC0_0000;
+const RECURSIVE_DIRECTORY: usize = 0xFFFF_F000;
+
+// two pages right below the recursive mapping, to reach frames that are not
+// in the direct map (tables of another address space, page copies...)
+const TEMPORARY_WINDOWS: usize = RECURSIVE_TABLES - 2 * PAGE_SIZE;
+
+extern "C" {
+    static stack_guard: u8;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagingError {
+    AlreadyMapped,
+    OutOfMemory,
+}
+
+#[repr(C, align(4096))]
+struct PageTable([u32; ENTRIES]);
+
+static mut KERNEL_PAGE_DIRECTORY: PageTable = PageTable([0; ENTRIES]);
+static PAGING_ENABLED: AtomicBool = AtomicBool::new(false);
+static DIRECT_MAP_END: AtomicUsize = AtomicUsize::new(BOOT_MAPPED_END);
+// how many address spaces map a user frame, only for frames mapped more than once
+static SHARED_FRAMES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+pub fn phys_to_virt(phys: usize) -> usize {
+    phys + KERNEL_BASE
+}
+
+pub fn virt_to_phys(virt: usize) -> usize {
+    virt - KERNEL_BASE
+}
+
+// physical memory below this address can be reached through phys_to_virt
+pub fn direct_map_end() -> usize {
+    DIRECT_MAP_END.load(Ordering::Relaxed)
+}
+
+fn directory_index(virt: usize) -> usize {
+    virt >> 22
+}
+
+fn table_index(virt: usize) -> usize {
+    (virt >> 12) & 0x3FF
+}
+
+fn directory() -> *mut u32 {
+    if PAGING_ENABLED.load(Ordering::Relaxed) {
+        RECURSIVE_DIRECTORY as *mut u32
+    } else {
+        addr_of_mut!(KERNEL_PAGE_DIRECTORY) as *mut u32
+    }
+}
+
+// until our directory is loaded, tables are reached through the boot mapping
+fn table(directory_index: usize) -> *mut u32 {
+    if PAGING_ENABLED.load(Ordering::Relaxed) {
+        (RECURSIVE_TABLES + directory_index * PAGE_SIZE) as *mut u32
+    } else {
+        let phys = unsafe { (*directory().add(directory_index) & !FLAGS_MASK) as usize };
+        assert!(phys < BOOT_MAPPED_END, "page table at {:#x} is not reachable during boot", phys);
+        phys_to_virt(phys) as *mut u32
+    }
+}
+
+pub fn flush_tlb(virt: usize) {
+    unsafe {
+        asm!("invlpg [{0}]", in(reg) virt, options(nostack, preserves_flags));
+    }
+}
+
+pub fn map_page(virt: usize, phys: usize, flags: u32) -> Result<(), PagingError> {
+    idt::without_interrupts(|| unsafe {
+        let pde = directory().add(directory_index(virt));
+        if *pde & PRESENT == 0 {
+            let frame = pmm::allocate_frame().ok_or(PagingError::OutOfMemory)?;
+            *pde = frame as u32 | PRESENT | WRITABLE | (flags & USER);
+            let new_table = table(directory_index(virt));
+            flush_tlb(new_table as usize);
+            core::ptr::write_bytes(new_table, 0, ENTRIES);
+        } else if flags & USER != 0 {
+            *pde |= USER;
+        }
+        let pte = table(directory_index(virt)).add(table_index(virt));
+        if *pte & PRESENT != 0 {
+            return Err(PagingError::AlreadyMapped);
+        }
+        *pte = (phys as u32 & !FLAGS_MASK) | (flags & FLAGS_MASK) | PRESENT;
+        flush_tlb(virt);
+        Ok(())
+    })
+}
+
+// returns the physical frame that was mapped, the caller decides whether to free it
+pub fn unmap_page(virt: usize) -> Option<usize> {
+    idt::without_interrupts(|| unsafe {
+        if *directory().add(directory_index(virt)) & PRESENT == 0 {
+            return None;
+        }
+        let pte = table(directory_index(virt)).add(table_index(virt));
+        if *pte & PRESENT == 0 {
+            return None;
+        }
+        let phys = (*pte & !FLAGS_MASK) as usize;
+        *pte = 0;
+        flush_tlb(virt);
+        Some(phys)
+    })
+}
+
+pub fn translate(virt: usize) -> Option<usize> {
+    unsafe {
+        if *directory().add(directory_index(virt)) & PRESENT == 0 {
+            return None;
+        }
+        let pte = *table(directory_index(virt)).add(table_index(virt));
+        if pte & PRESENT == 0 {
+            return None;
+        }
+        Some((pte & !FLAGS_MASK) as usize | (virt & (PAGE_SIZE - 1)))
+    }
+}
+
+// keeps the frame, replaces the flags
+pub fn set_page_flags(virt: usize, flags: u32) -> bool {
+    idt::without_interrupts(|| unsafe {
+        if *directory().add(directory_index(virt)) & PRESENT == 0 {
+            return false;
+        }
+        let pte = table(directory_index(virt)).add(table_index(virt));
+        if *pte & PRESENT == 0 {
+            return false;
+        }
+        *pte = (*pte & !FLAGS_MASK) | (flags & FLAGS_MASK) | PRESENT;
+        flush_tlb(virt);
+        true
+    })
+}
+
+// whether ring 3 may touch every byte of [start, start + len)
+pub fn user_accessible(start: usize, len: usize, write: bool) -> bool {
+    let end = match start.checked_add(len) {
+        Some(end) if end <= KERNEL_BASE => end,
+        _ => return false,
+    };
+    let required = PRESENT | USER;
+    for page in (start & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE) {
+        unsafe {
+            if *directory().add(directory_index(page)) & required != required {
+                return false;
+            }
+            let pte = *table(directory_index(page)).add(table_index(page));
+            if pte & required != required {
+                return false;
+            }
+            // copy on write pages become writable on the first write
+            if write && pte & (WRITABLE | COPY_ON_WRITE) == 0 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// maps fresh frames over [start, end)
+pub fn map_range(start: usize, end: usize, flags: u32) -> Result<(), PagingError> {
+    for page in (start & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE) {
+        let frame = pmm::allocate_frame().ok_or(PagingError::OutOfMemory)?;
+        if let Err(err) = map_page(page, frame, flags) {
+            pmm::free_frame(frame);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+// must be called with interrupts disabled, the windows are shared by everyone
+fn map_window(slot: usize, phys: usize) -> *mut u32 {
+    let virt = TEMPORARY_WINDOWS + slot * PAGE_SIZE;
+    unsafe {
+        *table(directory_index(virt)).add(table_index(virt)) = (phys as u32 & !FLAGS_MASK) | PRESENT | WRITABLE;
+    }
+    flush_tlb(virt);
+    virt as *mut u32
+}
+
+fn share_frame(frame: usize) {
+    *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+}
+
+// drops one mapping of a user frame, the frame is freed with the last one
+fn release_frame(frame: usize) {
+    let mut shared = SHARED_FRAMES.lock();
+    match shared.get_mut(&frame) {
+        Some(count) if *count > 2 => *count -= 1,
+        Some(_) => {
+            shared.remove(&frame);
+        }
+        None => pmm::free_frame(frame),
+    }
+}
+
+fn is_shared(frame: usize) -> bool {
+    SHARED_FRAMES.lock().contains_key(&frame)
+}
+
+// a directory with an empty user half, sharing the kernel page tables
+pub fn new_address_space() -> Result<usize, PagingError> {
+    idt::without_interrupts(|| unsafe {
+        let frame = pmm::allocate_frame().ok_or(PagingError::OutOfMemory)?;
+        let new_directory = map_window(0, frame);
+        core::ptr::write_bytes(new_directory, 0, KERNEL_DIRECTORY_INDEX);
+        for index in KERNEL_DIRECTORY_INDEX..RECURSIVE_INDEX {
+            *new_directory.add(index) = *directory().add(index);
+        }
+        *new_directory.add(RECURSIVE_INDEX) = frame as u32 | PRESENT | WRITABLE;
+        Ok(frame)
+    })
+}
+
+// copy of the current address space where every user page is shared,
+// writable ones become copy on write in both
+pub fn fork_address_space() -> Result<usize, PagingError> {
+    let child = new_address_space()?;
+    idt::without_interrupts(|| unsafe {
+        for index in 0..KERNEL_DIRECTORY_INDEX {
+            let pde = *directory().add(index);
+            if pde & PRESENT == 0 {
+                continue;
+            }
+            let table_frame = match pmm::allocate_frame() {
+                Some(frame) => frame,
+                None => {
+                    release_user_space(child);
+                    pmm::free_frame(child);
+                    return Err(PagingError::OutOfMemory);
+                }
+            };
+            *map_window(0, child).add(index) = table_frame as u32 | (pde & FLAGS_MASK);
+            let child_table = map_window(1, table_frame);
+            let parent_table = table(index);
+            for entry in 0..ENTRIES {
+                let mut pte = *parent_table.add(entry);
+                if pte & PRESENT != 0 {
+                    if pte & WRITABLE != 0 {
+                        pte = (pte & !WRITABLE) | COPY_ON_WRITE;
+                        *parent_table.add(entry) = pte;
+                    }
+                    share_frame((pte & !FLAGS_MASK) as usize);
+                }
+                *child_table.add(entry) = pte;
+            }
+        }
+        // the parent lost write access to its pages
+        switch_directory(current_directory());
+        Ok(child)
+    })
+}
+
+// unmaps every user page of an address space and frees its page tables,
+// the directory itself is left to the caller
+pub fn release_user_space(directory_phys: usize) {
+    idt::without_interrupts(|| unsafe {
+        for index in 0..KERNEL_DIRECTORY_INDEX {
+            let pde = *map_window(0, directory_phys).add(index);
+            if pde & PRESENT == 0 {
+                continue;
+            }
+            let table_frame = (pde & !FLAGS_MASK) as usize;
+            let user_table = map_window(1, table_frame);
+            for entry in 0..ENTRIES {
+                let pte = *user_table.add(entry);
+                if pte & PRESENT != 0 {
+                    release_frame((pte & !FLAGS_MASK) as usize);
+                }
+            }
+            pmm::free_frame(table_frame);
+            *map_window(0, directory_phys).add(index) = 0;
+        }
+        if directory_phys == current_directory() {
+            switch_directory(directory_phys);
+        }
+    });
+}
+
+// runs `f` with another address space loaded, to fill it from the kernel
+pub fn with_address_space<F: FnOnce() -> R, R>(directory_phys: usize, f: F) -> R {
+    idt::without_interrupts(|| {
+        let previous = current_directory();
+        switch_directory(directory_phys);
+        let result = f();
+        switch_directory(previous);
+        result
+    })
+}
+
+// gives a private copy of a copy on write page, false if `addr` is not one
+fn copy_on_write(addr: usize) -> bool {
+    let page = addr & !(PAGE_SIZE - 1);
+    idt::without_interrupts(|| unsafe {
+        if *directory().add(directory_index(page)) & PRESENT == 0 {
+            return false;
+        }
+        let pte = table(directory_index(page)).add(table_index(page));
+        if *pte & (PRESENT | COPY_ON_WRITE) != PRESENT | COPY_ON_WRITE {
+            return false;
+        }
+        let frame = (*pte & !FLAGS_MASK) as usize;
+        let flags = (*pte & FLAGS_MASK & !COPY_ON_WRITE) | WRITABLE;
+        if !is_shared(frame) {
+            // everybody else already has their own copy
+            *pte = frame as u32 | flags;
+        } else {
+            let copy = match pmm::allocate_frame() {
+                Some(copy) => copy,
+                None => return false,
+            };
+            core::ptr::copy_nonoverlapping(page as *const u8, map_window(0, copy) as *mut u8, PAGE_SIZE);
+            release_frame(frame);
+            *pte = copy as u32 | flags;
+        }
+        flush_tlb(page);
+        true
+    })
+}
+
+fn map_physical(start: usize, end: usize, flags: u32) {
+    for phys in (start & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE) {
+        match map_page(phys_to_virt(phys), phys, flags) {
+            Ok(()) | Err(PagingError::AlreadyMapped) => {},
+            Err(PagingError::OutOfMemory) => panic!("out of memory while mapping {:#x}", phys),
+        }
+    }
+}
+
+pub fn init_paging(boot_info: &BootInfo) {
+    let directory = unsafe { &mut (*addr_of_mut!(KERNEL_PAGE_DIRECTORY)).0 };
+    let directory_phys = virt_to_phys(directory.as_ptr() as usize);
+    directory[RECURSIVE_INDEX] = directory_phys as u32 | PRESENT | WRITABLE;
+    // every kernel page table exists from the start, so address spaces that
+    // copy the kernel half of this directory keep seeing the same mappings
+    for (index, entry) in directory.iter_mut().enumerate().take(RECURSIVE_INDEX).skip(KERNEL_DIRECTORY_INDEX) {
+        let frame = pmm::allocate_frame().expect("out of memory for kernel page tables");
+        *entry = frame as u32 | PRESENT | WRITABLE;
+        unsafe { core::ptr::write_bytes(table(index), 0, ENTRIES) };
+    }
+    // low memory (GDT at 0x800, VGA buffer at 0xb8000...), the kernel image
+    // and whatever GRUB loaded, all above KERNEL_BASE. Nothing below it stays mapped.
+    let (_, kernel_end) = pmm::kernel_bounds();
+    let mut end = kernel_end.max(boot_info.end_address()).max(BOOT_MAPPED_END);
+    for module in boot_info.modules() {
+        end = end.max(module.end as usize);
+    }
+    map_physical(0, end, WRITABLE);
+    let (guard, _) = boot_stack_guard();
+    unmap_page(guard);
+    unsafe {
+        asm!(
+            "mov cr3, {0}",
+            "mov {1}, cr0",
+            "or {1}, 0x10000", // WP: read-only pages are enforced in ring 0 too
+            "mov cr0, {1}",
+            in(reg) directory_phys,
+            out(reg) _,
+            options(nostack)
+        );
+    }
+    DIRECT_MAP_END.store((end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1), Ordering::Relaxed);
+    PAGING_ENABLED.store(true, Ordering::Relaxed);
+}
+
+// the unmapped page right below the boot stack
+pub fn boot_stack_guard() -> (usize, usize) {
+    let start = unsafe { &stack_guard as *const u8 as usize };
+    (start, start + PAGE_SIZE)
+}
+
+pub fn kernel_directory() -> usize {
+    virt_to_phys(addr_of!(KERNEL_PAGE_DIRECTORY) as usize)
+}
+
+// physical address of the page directory in use
+pub fn current_directory() -> usize {
+    let cr3: usize;
+    unsafe {
+        asm!("mov {0}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+    cr3
+}
+
+pub fn switch_directory(directory_phys: usize) {
+    unsafe {
+        asm!("mov cr3, {0}", in(reg) directory_phys, options(nostack, preserves_flags));
+    }
+}
+
+fn read_cr2() -> u32 {
+    let cr2: u32;
+    unsafe {
+        asm!("mov {0:e}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+    }
+    cr2
+}
+
+pub fn page_fault_handler(frame: &mut InterruptFrame) {
+    let addr = read_cr2() as usize;
+    let error = frame.error_code;
+    // a write to a present page of user space may just need its own copy
+    if error & 0b11 == 0b11 && addr < KERNEL_BASE && copy_on_write(addr) {
+        return;
+    }
+    unsafe { crate::vga_buffer::WRITER.force_unlock() };
+    println!("");
+    println!("PAGE FAULT at {:#010x}: {} {} {} in {} mode",
+        addr,
+        if error & (1 << 4) != 0 { "instruction fetch" } else if error & (1 << 1) != 0 { "write" } else { "read" },
+        if error & (1 << 0) != 0 { "violating the protection of" } else { "of" },
+        if error & (1 << 0) != 0 { "a present page" } else { "a non-present page" },
+        if error & (1 << 2) != 0 { "user" } else { "kernel" });
+    if error & (1 << 3) != 0 {
+        println!("reserved bit set in a paging structure");
+    }
+    idt::exception_handler(frame);
+}
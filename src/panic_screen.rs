@@ -0,0 +1,214 @@
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kfs_core::backtrace::Frames;
+use kfs_core::hal::TextScreen;
+
+use crate::paging::{self, KERNEL_BASE};
+use crate::serial;
+use crate::vga_buffer::{to_cp437, Color, ColorCode, ScreenChar, Writer, BUFFER_HEIGHT, BUFFER_WIDTH, WRITER};
+
+// What a panic leaves on the screen, whatever was there before: the message,
+// the registers and the return addresses up the stack. The same goes out on
+// the serial line, which may be all there is to read on a headless machine.
+// The addresses can be looked up with `addr2line -e isofiles/boot/kernel.bin`.
+
+// a panic while drawing the panic screen stops right there
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+// a few frames per line, so that a deep stack still fits on the screen
+const FRAMES_PER_LINE: usize = 4;
+
+macro_rules! read_register {
+    ($register:literal) => {{
+        let value: u32;
+        unsafe { asm!(concat!("mov {0:e}, ", $register), out(reg) value, options(nomem, nostack, preserves_flags)) };
+        value
+    }};
+}
+
+// as they were on the way into the panic handler, eax and friends have been
+// through the formatting of the message by then but may still tell something
+struct Registers {
+    eax: u32,
+    ebx: u32,
+    ecx: u32,
+    edx: u32,
+    esi: u32,
+    edi: u32,
+    ebp: u32,
+    esp: u32,
+    eflags: u32,
+    cr0: u32,
+    cr2: u32,
+    cr3: u32,
+    cr4: u32,
+}
+
+impl Registers {
+    #[inline(always)]
+    fn read() -> Registers {
+        let eflags: u32;
+        unsafe { asm!("pushfd", "pop {0:e}", out(reg) eflags, options(nomem, preserves_flags)) };
+        Registers {
+            eax: read_register!("eax"),
+            ebx: read_register!("ebx"),
+            ecx: read_register!("ecx"),
+            edx: read_register!("edx"),
+            esi: read_register!("esi"),
+            edi: read_register!("edi"),
+            ebp: read_register!("ebp"),
+            esp: read_register!("esp"),
+            eflags,
+            cr0: read_register!("cr0"),
+            cr2: read_register!("cr2"),
+            cr3: read_register!("cr3"),
+            cr4: read_register!("cr4"),
+        }
+    }
+}
+
+// white on red from the top left corner, what doesn't fit is only on the
+// serial line
+struct PanicScreen<'a> {
+    writer: &'a mut Writer,
+    row: usize,
+    col: usize,
+}
+
+impl<'a> PanicScreen<'a> {
+    fn new(writer: &'a mut Writer) -> PanicScreen<'a> {
+        writer.hide_pointer();
+        let blank = ScreenChar { ascii: b' ', color: ColorCode::new(Color::White, Color::Red) };
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                writer.write(row, col, blank);
+            }
+        }
+        PanicScreen { writer, row: 0, col: 0 }
+    }
+
+    fn title(&mut self, title: &str) {
+        let col = (BUFFER_WIDTH - title.len()) / 2;
+        for (index, byte) in title.bytes().enumerate() {
+            self.writer.write(self.row, col + index, ScreenChar { ascii: byte, color: ColorCode::new(Color::Red, Color::White) });
+        }
+        self.row += 2;
+    }
+}
+
+impl fmt::Write for PanicScreen<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' || self.col == BUFFER_WIDTH {
+                self.row += 1;
+                self.col = 0;
+            }
+            if c == '\n' || self.row >= BUFFER_HEIGHT {
+                continue;
+            }
+            let ascii = to_cp437(c).unwrap_or(b'?');
+            self.writer.write(self.row, self.col, ScreenChar { ascii, color: ColorCode::new(Color::White, Color::Red) });
+            self.col += 1;
+        }
+        Ok(())
+    }
+}
+
+// on the screen and the serial line
+macro_rules! report {
+    ($screen:expr, $($arg:tt)*) => {{
+        let _ = writeln!($screen, $($arg)*);
+        crate::serial_println!($($arg)*);
+    }};
+}
+
+// the kernel's own memory, unmapped pages read as the end of the chain
+fn read_stack(address: usize) -> u32 {
+    if paging::translate(address).is_none() {
+        return 0;
+    }
+    unsafe { *(address as *const u32) }
+}
+
+pub fn kernel_panic(info: &PanicInfo) -> ! {
+    let registers = Registers::read();
+    unsafe { asm!("cli", options(nomem, nostack)) };
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        halt();
+    }
+    // the panic may have happened while either was locked
+    unsafe {
+        WRITER.force_unlock();
+        serial::force_unlock();
+    }
+    let mut writer = WRITER.lock();
+    let mut screen = PanicScreen::new(&mut writer);
+    screen.title(" KERNEL PANIC ");
+    crate::serial_println!("KERNEL PANIC");
+    report!(screen, "{}", info.message());
+    if let Some(location) = info.location() {
+        report!(screen, "at {}:{}:{}", location.file(), location.line(), location.column());
+    }
+    report!(screen, "");
+    let r = &registers;
+    report!(screen, "EAX: {:#010x}  EBX: {:#010x}  ECX: {:#010x}  EDX: {:#010x}", r.eax, r.ebx, r.ecx, r.edx);
+    report!(screen, "ESI: {:#010x}  EDI: {:#010x}  EBP: {:#010x}  ESP: {:#010x}", r.esi, r.edi, r.ebp, r.esp);
+    report!(screen, "EFLAGS: {:#010x}", r.eflags);
+    report!(screen, "CR0: {:#010x}  CR2: {:#010x}  CR3: {:#010x}  CR4: {:#010x}", r.cr0, r.cr2, r.cr3, r.cr4);
+    report!(screen, "");
+    report!(screen, "Backtrace:");
+    let mut frames = Frames::new(r.ebp as usize, KERNEL_BASE, usize::MAX, read_stack).enumerate().peekable();
+    while frames.peek().is_some() {
+        let mut line = LineBuffer::new();
+        for (depth, eip) in frames.by_ref().take(FRAMES_PER_LINE) {
+            let _ = write!(line, "#{:<2} {:#010x}    ", depth, eip);
+        }
+        report!(screen, "  {}", line.as_str().trim_end());
+    }
+    report!(screen, "");
+    report!(screen, "System halted.");
+    drop(writer);
+    halt();
+}
+
+fn halt() -> ! {
+    loop {
+        unsafe {
+            asm!("cli", "hlt", options(nomem, nostack));
+        }
+    }
+}
+
+// a line of the backtrace, formatted once for both outputs, the heap may be
+// what panicked
+struct LineBuffer {
+    bytes: [u8; BUFFER_WIDTH],
+    len: usize,
+}
+
+impl LineBuffer {
+    fn new() -> LineBuffer {
+        LineBuffer { bytes: [0; BUFFER_WIDTH], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // only ever filled from a str, and cut on ASCII
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if self.len == self.bytes.len() {
+                return Err(fmt::Error);
+            }
+            self.bytes[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
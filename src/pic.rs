@@ -0,0 +1,88 @@
+use crate::io::{inb, outb};
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+
+const ICW1_INIT: u8 = 0x11; // initialization, ICW4 needed
+const ICW4_8086: u8 = 0x01;
+const PIC_EOI: u8 = 0x20;
+const PIC_READ_ISR: u8 = 0x0B;
+
+// vectors 0-31 are taken by the CPU exceptions
+pub const PIC1_OFFSET: u8 = 32;
+pub const PIC2_OFFSET: u8 = PIC1_OFFSET + 8;
+
+fn io_wait() {
+    // writing to an unused port gives the old PICs time to react
+    unsafe { outb(0x80, 0) };
+}
+
+pub fn init_pic() {
+    unsafe {
+        outb(PIC1_COMMAND, ICW1_INIT);
+        io_wait();
+        outb(PIC2_COMMAND, ICW1_INIT);
+        io_wait();
+        outb(PIC1_DATA, PIC1_OFFSET);
+        io_wait();
+        outb(PIC2_DATA, PIC2_OFFSET);
+        io_wait();
+        outb(PIC1_DATA, 4); // slave PIC is wired on IRQ2
+        io_wait();
+        outb(PIC2_DATA, 2); // slave PIC cascade identity
+        io_wait();
+        outb(PIC1_DATA, ICW4_8086);
+        io_wait();
+        outb(PIC2_DATA, ICW4_8086);
+        io_wait();
+
+        // every line masked except the cascade, drivers unmask what they use
+        outb(PIC1_DATA, 0xFB);
+        outb(PIC2_DATA, 0xFF);
+    }
+}
+
+pub fn enable_irq(irq: u8) {
+    unsafe {
+        if irq < 8 {
+            outb(PIC1_DATA, inb(PIC1_DATA) & !(1 << irq));
+        } else {
+            outb(PIC2_DATA, inb(PIC2_DATA) & !(1 << (irq - 8)));
+        }
+    }
+}
+
+fn read_isr() -> u16 {
+    unsafe {
+        outb(PIC1_COMMAND, PIC_READ_ISR);
+        outb(PIC2_COMMAND, PIC_READ_ISR);
+        ((inb(PIC2_COMMAND) as u16) << 8) | inb(PIC1_COMMAND) as u16
+    }
+}
+
+// IRQ7 and IRQ15 may be raised by a line that went down before the CPU
+// acknowledged it; in that case the in-service bit is not set
+pub fn is_spurious(irq: u8) -> bool {
+    if irq != 7 && irq != 15 {
+        return false;
+    }
+    if read_isr() & (1 << irq) != 0 {
+        return false;
+    }
+    if irq == 15 {
+        // the master did see the cascade line, it still wants its EOI
+        unsafe { outb(PIC1_COMMAND, PIC_EOI) };
+    }
+    true
+}
+
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outb(PIC2_COMMAND, PIC_EOI);
+        }
+        outb(PIC1_COMMAND, PIC_EOI);
+    }
+}
@@ -0,0 +1,151 @@
+extern crate spin;
+
+use self::spin::Mutex;
+
+use crate::idt::without_interrupts;
+use crate::multiboot::{BootInfo, MemoryAreaType};
+
+pub const FRAME_SIZE: usize = 4096;
+
+// one bit per 4 KiB frame, enough to cover the whole 32 bit address space
+const MAX_FRAMES: usize = 1 << 20;
+const BITMAP_WORDS: usize = MAX_FRAMES / 32;
+
+// everything below 1 MiB stays reserved: IVT, BIOS data, our GDT at 0x800, VGA memory...
+const LOW_MEMORY_END: usize = 0x100000;
+
+extern "C" {
+    static kernel_start: u8;
+    static kernel_end: u8;
+}
+
+pub struct FrameAllocator {
+    bitmap: [u32; BITMAP_WORDS], // a set bit means the frame is used
+    ram: [u32; BITMAP_WORDS], // a set bit means the frame is RAM, the rest is never handed out
+    total_frames: usize,
+    used_frames: usize,
+    next_free: usize,
+}
+
+impl FrameAllocator {
+    const fn new() -> FrameAllocator {
+        FrameAllocator {
+            bitmap: [0; BITMAP_WORDS],
+            ram: [0; BITMAP_WORDS],
+            total_frames: 0,
+            used_frames: 0,
+            next_free: 0,
+        }
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 32] & (1 << (frame % 32)) != 0
+    }
+
+    fn is_ram(&self, frame: usize) -> bool {
+        self.ram[frame / 32] & (1 << (frame % 32)) != 0
+    }
+
+    fn set_used(&mut self, frame: usize) {
+        self.bitmap[frame / 32] |= 1 << (frame % 32);
+    }
+
+    fn set_free(&mut self, frame: usize) {
+        self.bitmap[frame / 32] &= !(1 << (frame % 32));
+    }
+
+    fn add_area(&mut self, start: u64, end: u64) {
+        // only whole frames below 4 GiB are of any use to us
+        let end = end.min(MAX_FRAMES as u64 * FRAME_SIZE as u64);
+        let first = start.div_ceil(FRAME_SIZE as u64) as usize;
+        let last = (end / FRAME_SIZE as u64) as usize;
+        for frame in first..last {
+            if !self.is_ram(frame) {
+                self.ram[frame / 32] |= 1 << (frame % 32);
+                self.set_free(frame);
+                self.total_frames += 1;
+            }
+        }
+    }
+
+    fn reserve_area(&mut self, start: usize, end: usize) {
+        let first = start / FRAME_SIZE;
+        let last = end.div_ceil(FRAME_SIZE).min(MAX_FRAMES);
+        for frame in first..last {
+            if !self.is_used(frame) {
+                self.set_used(frame);
+                self.used_frames += 1;
+            }
+        }
+    }
+
+    fn allocate(&mut self) -> Option<usize> {
+        for word in (self.next_free / 32)..BITMAP_WORDS {
+            if self.bitmap[word] == u32::MAX {
+                continue;
+            }
+            let frame = word * 32 + (!self.bitmap[word]).trailing_zeros() as usize;
+            self.set_used(frame);
+            self.used_frames += 1;
+            self.next_free = frame + 1;
+            return Some(frame * FRAME_SIZE);
+        }
+        None
+    }
+
+    fn free(&mut self, addr: usize) {
+        let frame = addr / FRAME_SIZE;
+        // a hole in the memory map or a device, it was never ours to give
+        if frame >= MAX_FRAMES || !self.is_ram(frame) || !self.is_used(frame) {
+            return;
+        }
+        self.set_free(frame);
+        self.used_frames -= 1;
+        if frame < self.next_free {
+            self.next_free = frame;
+        }
+    }
+}
+
+static FRAME_ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
+
+pub fn kernel_bounds() -> (usize, usize) {
+    unsafe { (&kernel_start as *const u8 as usize, &kernel_end as *const u8 as usize) }
+}
+
+pub fn init_pmm(boot_info: &BootInfo) {
+    without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        allocator.bitmap.iter_mut().for_each(|word| *word = u32::MAX);
+        if let Some(memory_map) = boot_info.memory_map() {
+            for area in memory_map.filter(|area| area.typ == MemoryAreaType::Available) {
+                allocator.add_area(area.base_addr, area.base_addr + area.length);
+            }
+        }
+        let (start, end) = kernel_bounds();
+        allocator.reserve_area(0, LOW_MEMORY_END);
+        allocator.reserve_area(start, end);
+        allocator.reserve_area(boot_info.start_address(), boot_info.end_address());
+        for module in boot_info.modules() {
+            allocator.reserve_area(module.start as usize, module.end as usize);
+        }
+        allocator.next_free = 0;
+    });
+}
+
+// returns the physical address of a free 4 KiB frame
+pub fn allocate_frame() -> Option<usize> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate())
+}
+
+pub fn free_frame(addr: usize) {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().free(addr));
+}
+
+// (total, used, free) frame counts
+pub fn frame_stats() -> (usize, usize, usize) {
+    without_interrupts(|| {
+        let allocator = FRAME_ALLOCATOR.lock();
+        (allocator.total_frames, allocator.used_frames, allocator.total_frames - allocator.used_frames)
+    })
+}
@@ -0,0 +1,180 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use core::mem::{size_of, size_of_val};
+
+use crate::idt::InterruptFrame;
+use crate::paging;
+use crate::pmm;
+use crate::signal::SignalState;
+
+pub type Pid = u32;
+
+// 64 KiB each, there is no guard page below these since they live on the heap
+pub const KERNEL_STACK_SIZE: usize = 64 * 1024;
+const STACK_RED_ZONE: usize = 256;
+
+extern "C" {
+    // in interrupts.asm, pops an InterruptFrame and irets
+    fn interrupt_return();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Ready,
+    Running,
+    Blocked,
+    // by SIGSTOP and the like, until SIGCONT
+    Stopped,
+    // exited, waiting for its parent to collect the exit status
+    Zombie,
+    Exited,
+}
+
+impl ProcessState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessState::Ready => "ready",
+            ProcessState::Running => "running",
+            ProcessState::Blocked => "blocked",
+            ProcessState::Stopped => "stopped",
+            ProcessState::Zombie => "zombie",
+            ProcessState::Exited => "exited",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Exited(u8),
+    Killed(u8), // by a signal
+}
+
+impl ExitStatus {
+    // encoded like the status of UNIX wait()
+    pub fn wait_status(&self) -> u32 {
+        match *self {
+            ExitStatus::Exited(code) => (code as u32) << 8,
+            ExitStatus::Killed(signal) => signal as u32 & 0x7f,
+        }
+    }
+}
+
+// what switch_context (utils.asm) leaves on the stack of a task it switches away from
+#[repr(C)]
+struct Context {
+    edi: u32,
+    esi: u32,
+    ebx: u32,
+    ebp: u32,
+    eip: u32,
+}
+
+pub struct Process {
+    pub pid: Pid,
+    pub name: &'static str,
+    pub state: ProcessState,
+    pub parent: Option<Pid>,
+    pub exit_status: Option<ExitStatus>,
+    // saved esp, pointing to a Context while the process is not running
+    pub esp: u32,
+    // where interrupts from ring 3 land, see gdt::set_kernel_stack
+    pub esp0: u32,
+    // kernel threads all share the kernel directory, user processes have their own
+    pub page_directory: usize,
+    pub kernel_stack: Option<Box<[u8]>>,
+    pub signals: SignalState,
+}
+
+impl Process {
+    // the code running since boot, on the boot stack
+    pub fn boot(page_directory: usize, esp0: u32) -> Process {
+        Process {
+            pid: 0,
+            name: "idle",
+            state: ProcessState::Running,
+            parent: None,
+            exit_status: None,
+            esp: 0,
+            esp0,
+            page_directory,
+            kernel_stack: None,
+            signals: SignalState::new(),
+        }
+    }
+
+    // a fresh kernel stack with `data` copied at its top, the first
+    // switch_context to the process returns to `eip`
+    fn with_stack(pid: Pid, name: &'static str, parent: Pid, page_directory: usize, eip: u32, data: &[u32]) -> Process {
+        let mut stack = vec![0u8; KERNEL_STACK_SIZE].into_boxed_slice();
+        let top = stack.as_mut_ptr() as usize + KERNEL_STACK_SIZE;
+        let esp = top - size_of::<Context>() - size_of_val(data);
+        unsafe {
+            *(esp as *mut Context) = Context {
+                edi: 0,
+                esi: 0,
+                ebx: 0,
+                ebp: 0,
+                eip,
+            };
+            let above = (esp + size_of::<Context>()) as *mut u32;
+            core::ptr::copy_nonoverlapping(data.as_ptr(), above, data.len());
+        }
+        Process {
+            pid,
+            name,
+            state: ProcessState::Ready,
+            parent: Some(parent),
+            exit_status: None,
+            esp: esp as u32,
+            esp0: top as u32,
+            page_directory,
+            kernel_stack: Some(stack),
+            signals: SignalState::new(),
+        }
+    }
+
+    // a kernel thread that starts by calling task_entry(entry)
+    pub fn new(pid: Pid, name: &'static str, parent: Pid, page_directory: usize, entry: fn()) -> Process {
+        // task_entry finds a return address and its argument above the context
+        let data = [0, entry as usize as u32];
+        Process::with_stack(pid, name, parent, page_directory, task_entry as extern "C" fn(usize) -> ! as usize as u32, &data)
+    }
+
+    // a process that goes straight to ring 3 in the state saved in `frame`
+    pub fn new_user(pid: Pid, name: &'static str, parent: Pid, page_directory: usize, frame: &InterruptFrame) -> Process {
+        let data = unsafe {
+            core::slice::from_raw_parts(frame as *const InterruptFrame as *const u32, size_of::<InterruptFrame>() / size_of::<u32>())
+        };
+        Process::with_stack(pid, name, parent, page_directory, interrupt_return as unsafe extern "C" fn() as usize as u32, data)
+    }
+
+    pub fn has_user_space(&self) -> bool {
+        self.page_directory != paging::kernel_directory()
+    }
+
+    // there is no guard page, so the scheduler checks esp whenever it switches away
+    pub fn stack_overflowed(&self, esp: usize) -> bool {
+        match self.kernel_stack {
+            Some(ref stack) => esp < stack.as_ptr() as usize + STACK_RED_ZONE,
+            None => false,
+        }
+    }
+}
+
+// the user pages are released on exit, only the directory is left by then
+impl Drop for Process {
+    fn drop(&mut self) {
+        if self.has_user_space() {
+            pmm::free_frame(self.page_directory);
+        }
+    }
+}
+
+// first thing a new process runs, with interrupts still disabled by the scheduler
+// `entry` is the fn() that Process::new put on the stack, as a plain word
+extern "C" fn task_entry(entry: usize) -> ! {
+    let entry: fn() = unsafe { core::mem::transmute(entry) };
+    crate::idt::enable_interrupts();
+    entry();
+    crate::scheduler::exit(ExitStatus::Exited(0));
+}
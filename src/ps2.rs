@@ -0,0 +1,273 @@
+extern crate spin;
+
+use self::spin::Mutex;
+
+use crate::idt::without_interrupts;
+use crate::io::{inb, outb};
+use println;
+
+// The 8042 controller, with the keyboard on its first port and usually a
+// mouse on the second one. Nothing here waits forever, a missing controller
+// or device only costs a timeout.
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+// status register
+const OUTPUT_FULL: u8 = 0x01;
+const INPUT_FULL: u8 = 0x02;
+// the byte waiting in the output buffer comes from the second port
+const SECOND_OUTPUT_FULL: u8 = 0x20;
+
+// controller commands
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND: u8 = 0xA7;
+const ENABLE_SECOND: u8 = 0xA8;
+const TEST_SECOND: u8 = 0xA9;
+const TEST_CONTROLLER: u8 = 0xAA;
+const TEST_FIRST: u8 = 0xAB;
+const DISABLE_FIRST: u8 = 0xAD;
+const ENABLE_FIRST: u8 = 0xAE;
+const WRITE_SECOND: u8 = 0xD4;
+
+// controller configuration byte
+const FIRST_IRQ: u8 = 0x01;
+const SECOND_IRQ: u8 = 0x02;
+const SECOND_CLOCK_OFF: u8 = 0x20;
+// the keyboard speaks set 2, the controller turns it into the set 1 we decode
+const TRANSLATION: u8 = 0x40;
+
+const CONTROLLER_PASSED: u8 = 0x55;
+const PORT_PASSED: u8 = 0x00;
+
+// device commands and answers
+const RESET: u8 = 0xFF;
+pub const ACK: u8 = 0xFA;
+pub const RESEND: u8 = 0xFE;
+const SELF_TEST_PASSED: u8 = 0xAA;
+
+// roughly 100 ms of status port reads
+pub const TIMEOUT_POLLS: u32 = 100_000;
+// a reset runs the device's own self-test, which can take most of a second
+const RESET_POLLS: u32 = 1_000_000;
+// more than any sane controller has buffered
+const MAX_FLUSH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortStatus {
+    Absent,
+    // the interface test answer, 1-4 for a clock or data line stuck low or high
+    TestFailed(u8),
+    NoDevice,
+    // the device answered the reset with something else than a passed self-test
+    DeviceFailed(u8),
+    Ready,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+static PORTS: Mutex<[PortStatus; 2]> = Mutex::new([PortStatus::Absent; 2]);
+
+fn read_status() -> u8 {
+    unsafe { inb(STATUS_PORT) }
+}
+
+// None when the controller has nothing for us
+fn poll_data() -> Option<u8> {
+    if (read_status() & OUTPUT_FULL) == 0 {
+        return None;
+    }
+    Some(unsafe { inb(DATA_PORT) })
+}
+
+// the next byte and the port it came from
+pub fn poll_port() -> Option<(Port, u8)> {
+    let status = read_status();
+    if (status & OUTPUT_FULL) == 0 {
+        return None;
+    }
+    let port = if (status & SECOND_OUTPUT_FULL) != 0 { Port::Second } else { Port::First };
+    Some((port, unsafe { inb(DATA_PORT) }))
+}
+
+fn wait_data(polls: u32) -> Result<u8, Timeout> {
+    for _ in 0..polls {
+        if let Some(byte) = poll_data() {
+            return Ok(byte);
+        }
+    }
+    Err(Timeout)
+}
+
+pub fn read_data() -> Result<u8, Timeout> {
+    wait_data(TIMEOUT_POLLS)
+}
+
+// until the controller has taken the last byte we gave it
+fn wait_input_empty() -> Result<(), Timeout> {
+    for _ in 0..TIMEOUT_POLLS {
+        if (read_status() & INPUT_FULL) == 0 {
+            return Ok(());
+        }
+    }
+    Err(Timeout)
+}
+
+fn send_command(command: u8) -> Result<(), Timeout> {
+    wait_input_empty()?;
+    unsafe { outb(COMMAND_PORT, command) };
+    Ok(())
+}
+
+fn command_response(command: u8) -> Result<u8, Timeout> {
+    send_command(command)?;
+    read_data()
+}
+
+fn write_config(config: u8) -> Result<(), Timeout> {
+    send_command(WRITE_CONFIG)?;
+    wait_input_empty()?;
+    unsafe { outb(DATA_PORT, config) };
+    Ok(())
+}
+
+// to the device on the given port
+pub fn write_data(port: Port, value: u8) -> Result<(), Timeout> {
+    if port == Port::Second {
+        send_command(WRITE_SECOND)?;
+    }
+    wait_input_empty()?;
+    unsafe { outb(DATA_PORT, value) };
+    Ok(())
+}
+
+fn flush() {
+    for _ in 0..MAX_FLUSH {
+        if poll_data().is_none() {
+            return;
+        }
+    }
+}
+
+pub fn port_status(port: Port) -> PortStatus {
+    without_interrupts(|| PORTS.lock()[port as usize])
+}
+
+pub fn port_ready(port: Port) -> bool {
+    port_status(port) == PortStatus::Ready
+}
+
+pub fn init_ps2() {
+    match init_controller() {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(Timeout) => {
+            println!("PS/2 controller: not responding");
+            return;
+        }
+    }
+    for &port in [Port::First, Port::Second].iter() {
+        let number = port as usize + 1;
+        match port_status(port) {
+            PortStatus::Absent => println!("PS/2 port {}: not present", number),
+            PortStatus::TestFailed(code) => println!("PS/2 port {}: interface test failed ({:#04x})", number, code),
+            PortStatus::NoDevice => println!("PS/2 port {}: no device", number),
+            PortStatus::DeviceFailed(code) => println!("PS/2 port {}: device self-test failed ({:#04x})", number, code),
+            PortStatus::Ready => println!("PS/2 port {}: ok", number),
+        }
+    }
+}
+
+// false if the controller failed its self-test
+fn init_controller() -> Result<bool, Timeout> {
+    // nothing must come in while we set things up
+    send_command(DISABLE_FIRST)?;
+    send_command(DISABLE_SECOND)?;
+    flush();
+
+    let mut config = command_response(READ_CONFIG)?;
+    config &= !(FIRST_IRQ | SECOND_IRQ);
+    config |= TRANSLATION;
+    // the second clock only stays off if there is a second port to turn off
+    let mut dual = config & SECOND_CLOCK_OFF != 0;
+    write_config(config)?;
+
+    let result = command_response(TEST_CONTROLLER)?;
+    // some controllers come out of the self-test reset
+    write_config(config)?;
+    if result != CONTROLLER_PASSED {
+        println!("PS/2 controller: self-test failed ({:#04x})", result);
+        return Ok(false);
+    }
+
+    if dual {
+        send_command(ENABLE_SECOND)?;
+        dual = command_response(READ_CONFIG)? & SECOND_CLOCK_OFF == 0;
+        send_command(DISABLE_SECOND)?;
+        write_config(config)?;
+    }
+
+    let mut ports = [PortStatus::Absent; 2];
+    ports[Port::First as usize] = test_port(TEST_FIRST)?;
+    if dual {
+        ports[Port::Second as usize] = test_port(TEST_SECOND)?;
+    }
+
+    // only the ports that passed their test
+    if ports[Port::First as usize] == PortStatus::NoDevice {
+        send_command(ENABLE_FIRST)?;
+        config |= FIRST_IRQ;
+    }
+    if ports[Port::Second as usize] == PortStatus::NoDevice {
+        send_command(ENABLE_SECOND)?;
+        config |= SECOND_IRQ;
+    }
+    for &port in [Port::First, Port::Second].iter() {
+        if ports[port as usize] == PortStatus::NoDevice {
+            ports[port as usize] = reset_device(port);
+        }
+    }
+    // the PIC still masks both lines until their drivers take them
+    write_config(config)?;
+    flush();
+    without_interrupts(|| *PORTS.lock() = ports);
+    Ok(true)
+}
+
+// NoDevice until a device answers a reset
+fn test_port(command: u8) -> Result<PortStatus, Timeout> {
+    Ok(match command_response(command)? {
+        PORT_PASSED => PortStatus::NoDevice,
+        code => PortStatus::TestFailed(code),
+    })
+}
+
+fn reset_device(port: Port) -> PortStatus {
+    if write_data(port, RESET).is_err() {
+        return PortStatus::NoDevice;
+    }
+    // ACK and the self-test result, usually in that order
+    let (mut acked, mut passed) = (false, false);
+    while !(acked && passed) {
+        match wait_data(RESET_POLLS) {
+            Ok(ACK) => acked = true,
+            Ok(SELF_TEST_PASSED) => passed = true,
+            Ok(code) if acked => return PortStatus::DeviceFailed(code),
+            _ => return PortStatus::NoDevice,
+        }
+    }
+    // a mouse follows with its id
+    if port == Port::Second {
+        let _ = read_data();
+    }
+    PortStatus::Ready
+}
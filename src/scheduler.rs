@@ -0,0 +1,359 @@
+extern crate spin;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use self::spin::Mutex;
+
+use crate::gdt;
+use crate::idt::{self, without_interrupts, InterruptFrame};
+use crate::paging::{self, PagingError};
+use crate::process::{ExitStatus, Pid, Process, ProcessState};
+use crate::thread::{Interrupted, WaitQueue};
+use crate::timer;
+
+// how long a process runs before the timer hands the CPU to the next one
+const TIME_SLICE_MS: u32 = 10;
+const IDLE_PID: Pid = 0;
+
+// TIME_SLICE_MS at the timer's frequency, set by init_scheduler
+static SLICE_TICKS: AtomicU32 = AtomicU32::new(1);
+
+extern "C" {
+    fn switch_context(old_esp: *mut u32, new_esp: u32);
+}
+
+// processes are boxed so the saved esp we give to switch_context stays put
+// when the vector moves. The idle process is always the first one.
+struct Scheduler {
+    #[allow(clippy::vec_box)]
+    processes: Vec<Box<Process>>,
+    current: usize,
+    next_pid: Pid,
+}
+
+impl Scheduler {
+    const fn new() -> Scheduler {
+        Scheduler {
+            processes: Vec::new(),
+            current: 0,
+            next_pid: IDLE_PID + 1,
+        }
+    }
+
+    // round robin over everything that can run, idle only when nothing else can
+    fn next_index(&self) -> usize {
+        let count = self.processes.len();
+        for offset in 1..=count {
+            let index = (self.current + offset) % count;
+            let process = &self.processes[index];
+            if process.pid == IDLE_PID {
+                continue;
+            }
+            if process.state == ProcessState::Ready || (index == self.current && process.state == ProcessState::Running) {
+                return index;
+            }
+        }
+        0
+    }
+
+    // frees the stacks of exited processes, except ours since we are still on it
+    fn reap(&mut self) {
+        let current_pid = self.processes[self.current].pid;
+        self.processes.retain(|p| p.state != ProcessState::Exited || p.pid == current_pid);
+        self.current = self.processes.iter().position(|p| p.pid == current_pid).unwrap_or(0);
+    }
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+// woken up whenever a process exits
+static EXITED: WaitQueue = WaitQueue::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    NoChild,
+    // a signal came in first
+    Interrupted,
+}
+
+impl From<Interrupted> for WaitError {
+    fn from(_: Interrupted) -> WaitError {
+        WaitError::Interrupted
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessInfo {
+    pub pid: Pid,
+    pub parent: Option<Pid>,
+    pub state: ProcessState,
+    pub name: &'static str,
+}
+
+fn current_esp() -> usize {
+    let esp: usize;
+    unsafe {
+        asm!("mov {0}, esp", out(reg) esp, options(nomem, nostack, preserves_flags));
+    }
+    esp
+}
+
+// turns the code running since boot into the idle process
+pub fn init_scheduler() {
+    SLICE_TICKS.store(timer::ms_to_ticks(TIME_SLICE_MS), Ordering::Relaxed);
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let idle = Process::boot(paging::current_directory(), gdt::kernel_stack());
+        scheduler.processes.push(Box::new(idle));
+        scheduler.current = 0;
+    });
+}
+
+// `create` is given the new pid and its parent, the current process
+fn add_process<F: FnOnce(Pid, Pid) -> Process>(create: F) -> Pid {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let pid = scheduler.next_pid;
+        scheduler.next_pid += 1;
+        let parent = scheduler.processes.get(scheduler.current).map_or(IDLE_PID, |p| p.pid);
+        scheduler.processes.push(Box::new(create(pid, parent)));
+        pid
+    })
+}
+
+pub fn spawn(name: &'static str, entry: fn()) -> Pid {
+    add_process(|pid, parent| Process::new(pid, name, parent, paging::kernel_directory(), entry))
+}
+
+// a process running in ring 3 with its own address space, see usermode.rs
+pub fn spawn_user(name: &'static str, page_directory: usize, frame: &InterruptFrame) -> Pid {
+    add_process(|pid, parent| Process::new_user(pid, name, parent, page_directory, frame))
+}
+
+// the child gets a copy on write copy of our address space and resumes
+// from the same system call, with 0 instead of its pid in eax
+pub fn fork(frame: &InterruptFrame) -> Result<Pid, PagingError> {
+    let page_directory = paging::fork_address_space()?;
+    let (name, signals) = with_current(|process| (process.name, process.signals.forked()));
+    let mut child_frame = *frame;
+    child_frame.eax = 0;
+    Ok(add_process(|pid, parent| {
+        let mut child = Process::new_user(pid, name, parent, page_directory, &child_frame);
+        child.signals = signals;
+        child
+    }))
+}
+
+// for exec, gives back the directory the process had before
+pub fn replace_address_space(name: &'static str, page_directory: usize) -> usize {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        let process = &mut scheduler.processes[current];
+        let old = core::mem::replace(&mut process.page_directory, page_directory);
+        process.name = name;
+        process.signals.reset_handlers();
+        paging::switch_directory(page_directory);
+        old
+    })
+}
+
+pub fn with_current<R, F: FnOnce(&mut Process) -> R>(f: F) -> R {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        f(&mut scheduler.processes[current])
+    })
+}
+
+// None if there is no such process
+pub fn with_process<R, F: FnOnce(&mut Process) -> R>(pid: Pid, f: F) -> Option<R> {
+    without_interrupts(|| {
+        SCHEDULER.lock().processes.iter_mut().find(|p| p.pid == pid).map(|p| f(p))
+    })
+}
+
+// whether the current process should stop waiting to handle a signal
+pub fn signal_pending() -> bool {
+    with_current(|process| process.signals.has_deliverable())
+}
+
+pub fn current_pid() -> Pid {
+    without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        scheduler.processes.get(scheduler.current).map_or(IDLE_PID, |p| p.pid)
+    })
+}
+
+pub fn parent_pid() -> Pid {
+    without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        scheduler.processes.get(scheduler.current).and_then(|p| p.parent).unwrap_or(IDLE_PID)
+    })
+}
+
+pub fn processes() -> Vec<ProcessInfo> {
+    without_interrupts(|| {
+        SCHEDULER.lock().processes.iter().map(|p| ProcessInfo {
+            pid: p.pid,
+            parent: p.parent,
+            state: p.state,
+            name: p.name,
+        }).collect()
+    })
+}
+
+// gives the CPU to the next process, returns when our turn comes back
+pub fn schedule() {
+    without_interrupts(|| {
+        let (old_esp, new_esp) = {
+            let mut scheduler = SCHEDULER.lock();
+            if scheduler.processes.is_empty() {
+                return;
+            }
+            scheduler.reap();
+            let next = scheduler.next_index();
+            let current = scheduler.current;
+            if next == current {
+                return;
+            }
+            let esp0 = gdt::kernel_stack();
+            let old = &mut scheduler.processes[current];
+            if old.state == ProcessState::Running {
+                old.state = ProcessState::Ready;
+            }
+            old.esp0 = esp0;
+            if old.stack_overflowed(current_esp()) {
+                panic!("kernel stack overflow in process {} ({})", old.pid, old.name);
+            }
+            let old_directory = old.page_directory;
+            let old_esp = &mut old.esp as *mut u32;
+            let new = &mut scheduler.processes[next];
+            new.state = ProcessState::Running;
+            gdt::set_kernel_stack(new.esp0);
+            if new.page_directory != old_directory {
+                paging::switch_directory(new.page_directory);
+            }
+            let new_esp = new.esp;
+            scheduler.current = next;
+            (old_esp, new_esp)
+        };
+        unsafe { switch_context(old_esp, new_esp) };
+    });
+}
+
+// must be called with interrupts disabled, after registering somewhere we
+// will be woken up from. The idle process cannot block, it just waits for
+// the next interrupt instead.
+pub fn block_current() {
+    let blocked = {
+        let mut scheduler = SCHEDULER.lock();
+        let current = scheduler.current;
+        match scheduler.processes.get_mut(current) {
+            Some(process) if process.pid != IDLE_PID => {
+                process.state = ProcessState::Blocked;
+                true
+            }
+            _ => false,
+        }
+    };
+    if blocked {
+        schedule();
+    } else {
+        idt::enable_interrupts_and_hlt();
+        idt::disable_interrupts();
+    }
+}
+
+pub fn wake(pid: Pid) {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if let Some(process) = scheduler.processes.iter_mut().find(|p| p.pid == pid) {
+            if process.state == ProcessState::Blocked {
+                process.state = ProcessState::Ready;
+            }
+        }
+    });
+}
+
+pub fn exit(status: ExitStatus) -> ! {
+    let page_directory = without_interrupts(|| {
+        let scheduler = SCHEDULER.lock();
+        scheduler.processes[scheduler.current].page_directory
+    });
+    if page_directory != paging::kernel_directory() {
+        paging::release_user_space(page_directory);
+    }
+    // our stack may be freed as soon as our parent sees we are a zombie,
+    // so we must not run again once we are marked as one
+    without_interrupts(|| {
+        {
+            let mut scheduler = SCHEDULER.lock();
+            let current = scheduler.current;
+            let process = &mut scheduler.processes[current];
+            let pid = process.pid;
+            process.exit_status = Some(status);
+            // nobody will wait for children of the idle process
+            process.state = match process.parent {
+                Some(parent) if parent != IDLE_PID => ProcessState::Zombie,
+                _ => ProcessState::Exited,
+            };
+            // orphans are adopted by the idle process
+            for process in scheduler.processes.iter_mut().filter(|p| p.parent == Some(pid)) {
+                process.parent = Some(IDLE_PID);
+                if process.state == ProcessState::Zombie {
+                    process.state = ProcessState::Exited;
+                }
+            }
+        }
+        EXITED.wake_all();
+        schedule();
+    });
+    unreachable!("an exited process was scheduled again");
+}
+
+// nobody is going to wait for `pid`, it is reaped as soon as it exits
+pub fn detach(pid: Pid) {
+    without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        if let Some(process) = scheduler.processes.iter_mut().find(|p| p.pid == pid) {
+            process.parent = Some(IDLE_PID);
+            if process.state == ProcessState::Zombie {
+                process.state = ProcessState::Exited;
+            }
+        }
+    });
+}
+
+// waits for a child (any of them when `pid` is None) to exit and reaps it
+pub fn waitpid(pid: Option<Pid>) -> Result<(Pid, ExitStatus), WaitError> {
+    let parent = current_pid();
+    let mut result = Err(WaitError::NoChild);
+    EXITED.wait_until(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let mut children = scheduler.processes.iter_mut()
+            .filter(|p| p.parent == Some(parent) && p.state != ProcessState::Exited && pid.is_none_or(|pid| p.pid == pid))
+            .peekable();
+        if children.peek().is_none() {
+            return true;
+        }
+        match children.find(|p| p.state == ProcessState::Zombie) {
+            Some(zombie) => {
+                zombie.state = ProcessState::Exited;
+                result = Ok((zombie.pid, zombie.exit_status.unwrap_or(ExitStatus::Exited(0))));
+                true
+            }
+            None => false,
+        }
+    })?;
+    result
+}
+
+// called by the timer interrupt on every tick
+pub fn timer_tick(ticks: u32) {
+    if ticks.is_multiple_of(SLICE_TICKS.load(Ordering::Relaxed)) {
+        schedule();
+    }
+}
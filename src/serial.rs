@@ -0,0 +1,262 @@
+extern crate spin;
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use self::spin::Mutex;
+
+use crate::idt::{self, without_interrupts, InterruptFrame};
+use crate::io::{inb, outb};
+use crate::keyboard;
+use crate::multiboot;
+use crate::pic;
+use crate::signal;
+
+// The first serial port, a 16550 UART. Output goes out a byte at a time,
+// input comes from its interrupt and is read by the shell like the keyboard.
+
+const COM1: u16 = 0x3F8;
+const COM1_IRQ: u8 = 4;
+
+// registers, from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+// with DLAB set in the line control register
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+
+const DLAB: u8 = 0x80;
+// 8 data bits, no parity, 1 stop bit
+const EIGHT_N_ONE: u8 = 0x03;
+// enable and clear both FIFOs, interrupt when 14 bytes are waiting
+const FIFO_ENABLE: u8 = 0xC7;
+// DTR, RTS and OUT2, which lets the interrupt through to the PIC
+const MODEM_READY: u8 = 0x0B;
+// what we write comes back, nothing goes out on the line
+const LOOPBACK: u8 = 0x1E;
+const DATA_AVAILABLE_INTERRUPT: u8 = 0x01;
+
+// line status
+const DATA_READY: u8 = 0x01;
+const TRANSMIT_EMPTY: u8 = 0x20;
+
+const CLOCK: u32 = 115200;
+pub const DEFAULT_BAUD: u32 = 115200;
+// the UART sends about one byte every 100 us at 115200 baud, this is plenty
+const TRANSMIT_POLLS: u32 = 100_000;
+const INPUT_SIZE: usize = 64;
+const CTRL_C: u8 = 0x03;
+
+static PRESENT: AtomicBool = AtomicBool::new(false);
+static BAUD: AtomicU32 = AtomicU32::new(DEFAULT_BAUD);
+// whether print! goes to the serial line too
+static MIRROR: AtomicBool = AtomicBool::new(false);
+static PORT: Mutex<SerialPort> = Mutex::new(SerialPort { base: COM1 });
+static INPUT: Mutex<InputQueue> = Mutex::new(InputQueue { bytes: [0; INPUT_SIZE], start: 0, len: 0 });
+
+struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    fn read(&self, register: u16) -> u8 {
+        unsafe { inb(self.base + register) }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        unsafe { outb(self.base + register, value) }
+    }
+
+    // false if there is no UART there
+    fn init(&mut self, baud: u32) -> bool {
+        let divisor = (CLOCK / baud) as u16;
+        self.write(INTERRUPT_ENABLE, 0);
+        self.write(LINE_CONTROL, DLAB);
+        self.write(DIVISOR_LOW, divisor as u8);
+        self.write(DIVISOR_HIGH, (divisor >> 8) as u8);
+        self.write(LINE_CONTROL, EIGHT_N_ONE);
+        self.write(FIFO_CONTROL, FIFO_ENABLE);
+        self.write(MODEM_CONTROL, LOOPBACK);
+        self.write(DATA, 0xAE);
+        if self.read(DATA) != 0xAE {
+            return false;
+        }
+        self.write(MODEM_CONTROL, MODEM_READY);
+        self.write(INTERRUPT_ENABLE, DATA_AVAILABLE_INTERRUPT);
+        true
+    }
+
+    // a byte is dropped if the line doesn't move, rather than hang the kernel
+    fn send(&mut self, byte: u8) {
+        for _ in 0..TRANSMIT_POLLS {
+            if self.read(LINE_STATUS) & TRANSMIT_EMPTY != 0 {
+                self.write(DATA, byte);
+                return;
+            }
+        }
+    }
+
+    fn receive(&self) -> Option<u8> {
+        if self.read(LINE_STATUS) & DATA_READY == 0 {
+            return None;
+        }
+        Some(self.read(DATA))
+    }
+
+    // terminals want \r\n, and a backspace that erases
+    fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => {
+                self.send(b'\r');
+                self.send(b'\n');
+            }
+            b'\x08' => {
+                self.send(b'\x08');
+                self.send(b' ');
+                self.send(b'\x08');
+            }
+            byte => self.send(byte),
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+// what came in and the shell hasn't read yet, dropped when full
+struct InputQueue {
+    bytes: [u8; INPUT_SIZE],
+    start: usize,
+    len: usize,
+}
+
+fn serial_interrupt(_frame: &mut InterruptFrame) {
+    let mut received = false;
+    loop {
+        let byte = match PORT.lock().receive() {
+            Some(byte) => byte,
+            None => break,
+        };
+        // Ctrl+C, like on the keyboard
+        if byte == CTRL_C && signal::interrupt_foreground() {
+            continue;
+        }
+        let mut input = INPUT.lock();
+        if input.len < INPUT_SIZE {
+            let end = (input.start + input.len) % INPUT_SIZE;
+            input.bytes[end] = byte;
+            input.len += 1;
+        }
+        received = true;
+    }
+    if received {
+        keyboard::wake_readers();
+    }
+}
+
+// `console=ttyS0` on the kernel command line mirrors the console to COM1,
+// `console=ttyS0,38400` sets the speed too
+pub fn init_serial() {
+    let mut baud = DEFAULT_BAUD;
+    let command_line = multiboot::boot_info().and_then(|info| info.command_line()).unwrap_or("");
+    for option in command_line.split_whitespace().filter_map(|arg| arg.strip_prefix("console=ttyS0")) {
+        MIRROR.store(true, Ordering::Relaxed);
+        let speed = match option.strip_prefix(',') {
+            Some(speed) => speed,
+            None => continue,
+        };
+        // the divisor has to be a whole number
+        match speed.parse::<u32>() {
+            Ok(speed) if speed > 0 && speed <= CLOCK && CLOCK.is_multiple_of(speed) => baud = speed,
+            _ => crate::println!("Unsupported serial speed {}, using {}", speed, DEFAULT_BAUD),
+        }
+    }
+    let present = without_interrupts(|| PORT.lock().init(baud));
+    PRESENT.store(present, Ordering::Relaxed);
+    BAUD.store(baud, Ordering::Relaxed);
+    if present {
+        idt::register_irq_handler(COM1_IRQ, serial_interrupt);
+        pic::enable_irq(COM1_IRQ);
+    }
+}
+
+pub fn is_present() -> bool {
+    PRESENT.load(Ordering::Relaxed)
+}
+
+pub fn baud() -> u32 {
+    BAUD.load(Ordering::Relaxed)
+}
+
+pub fn is_mirroring() -> bool {
+    MIRROR.load(Ordering::Relaxed)
+}
+
+pub fn set_mirroring(mirror: bool) {
+    MIRROR.store(mirror, Ordering::Relaxed);
+}
+
+pub fn has_input() -> bool {
+    without_interrupts(|| INPUT.lock().len > 0)
+}
+
+pub fn read_byte() -> Option<u8> {
+    without_interrupts(|| {
+        let mut input = INPUT.lock();
+        if input.len == 0 {
+            return None;
+        }
+        let byte = input.bytes[input.start];
+        input.start = (input.start + 1) % INPUT_SIZE;
+        input.len -= 1;
+        Some(byte)
+    })
+}
+
+// for the panic screen, whoever held the port isn't coming back
+pub unsafe fn force_unlock() {
+    PORT.force_unlock();
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+// what user programs write, which doesn't have to be UTF-8
+pub fn write_bytes(bytes: &[u8]) {
+    if !is_present() {
+        return;
+    }
+    without_interrupts(|| {
+        let mut port = PORT.lock();
+        for &byte in bytes {
+            port.write_byte(byte);
+        }
+    });
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    if !is_present() {
+        return;
+    }
+    without_interrupts(|| PORT.lock().write_fmt(args).unwrap());
+}
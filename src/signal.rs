@@ -0,0 +1,392 @@
+use core::mem::size_of;
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::idt::InterruptFrame;
+use crate::paging;
+use crate::process::{ExitStatus, Pid, ProcessState};
+use crate::scheduler;
+use crate::syscall::SYS_SIGRETURN;
+
+// POSIX-like signals for user processes. A signal is pending until the
+// process goes back to ring 3 with it unblocked, it is then handled on the
+// way out of interrupt_handler.
+
+pub const SIGHUP: u8 = 1;
+pub const SIGINT: u8 = 2;
+pub const SIGQUIT: u8 = 3;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGABRT: u8 = 6;
+pub const SIGBUS: u8 = 7;
+pub const SIGFPE: u8 = 8;
+pub const SIGKILL: u8 = 9;
+pub const SIGUSR1: u8 = 10;
+pub const SIGSEGV: u8 = 11;
+pub const SIGUSR2: u8 = 12;
+pub const SIGPIPE: u8 = 13;
+pub const SIGALRM: u8 = 14;
+pub const SIGTERM: u8 = 15;
+pub const SIGCHLD: u8 = 17;
+pub const SIGCONT: u8 = 18;
+pub const SIGSTOP: u8 = 19;
+pub const SIGTSTP: u8 = 20;
+pub const SIGTTIN: u8 = 21;
+pub const SIGTTOU: u8 = 22;
+pub const SIGWINCH: u8 = 28;
+// signals are 1 to NSIG - 1, one bit each in a u32 mask
+pub const NSIG: u8 = 32;
+
+const SIGNAL_NAMES: [(&str, u8); 22] = [
+    ("HUP", SIGHUP),
+    ("INT", SIGINT),
+    ("QUIT", SIGQUIT),
+    ("ILL", SIGILL),
+    ("TRAP", SIGTRAP),
+    ("ABRT", SIGABRT),
+    ("BUS", SIGBUS),
+    ("FPE", SIGFPE),
+    ("KILL", SIGKILL),
+    ("USR1", SIGUSR1),
+    ("SEGV", SIGSEGV),
+    ("USR2", SIGUSR2),
+    ("PIPE", SIGPIPE),
+    ("ALRM", SIGALRM),
+    ("TERM", SIGTERM),
+    ("CHLD", SIGCHLD),
+    ("CONT", SIGCONT),
+    ("STOP", SIGSTOP),
+    ("TSTP", SIGTSTP),
+    ("TTIN", SIGTTIN),
+    ("TTOU", SIGTTOU),
+    ("WINCH", SIGWINCH),
+];
+
+pub const SIG_DFL: u32 = 0;
+pub const SIG_IGN: u32 = 1;
+
+pub const SA_NODEFER: u32 = 0x4000_0000; // the signal is not blocked while its handler runs
+pub const SA_RESETHAND: u32 = 0x8000_0000; // the handler is reset to SIG_DFL once called
+
+pub const SIG_BLOCK: u32 = 0;
+pub const SIG_UNBLOCK: u32 = 1;
+pub const SIG_SETMASK: u32 = 2;
+
+// the arithmetic flags and DF, a handler cannot change the others through sigreturn
+const RESTORABLE_FLAGS: u32 = 0xcd5;
+const DIRECTION_FLAG: u32 = 1 << 10;
+
+const fn bit(signal: u8) -> u32 {
+    1 << signal
+}
+
+// neither caught, blocked nor ignored
+const UNCATCHABLE: u32 = bit(SIGKILL) | bit(SIGSTOP);
+const STOP_SIGNALS: u32 = bit(SIGSTOP) | bit(SIGTSTP) | bit(SIGTTIN) | bit(SIGTTOU);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+pub fn default_action(signal: u8) -> DefaultAction {
+    match signal {
+        SIGCHLD | SIGWINCH => DefaultAction::Ignore,
+        SIGCONT => DefaultAction::Continue,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+pub fn is_valid(signal: u32) -> bool {
+    signal > 0 && signal < NSIG as u32
+}
+
+pub fn is_catchable(signal: u32) -> bool {
+    is_valid(signal) && bit(signal as u8) & UNCATCHABLE == 0
+}
+
+// without the SIG prefix
+pub fn name(signal: u8) -> Option<&'static str> {
+    SIGNAL_NAMES.iter().find(|&&(_, number)| number == signal).map(|&(name, _)| name)
+}
+
+// "INT", "SIGINT" or "2"
+pub fn from_name(name: &str) -> Option<u8> {
+    let name = name.strip_prefix("SIG").unwrap_or(name);
+    match name.parse::<u8>() {
+        Ok(signal) if is_valid(signal as u32) => Some(signal),
+        Ok(_) => None,
+        Err(_) => SIGNAL_NAMES.iter().find(|&&(known, _)| known == name).map(|&(_, signal)| signal),
+    }
+}
+
+// struct sigaction as user programs give it to us
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct SigAction {
+    pub handler: u32,
+    pub mask: u32,
+    pub flags: u32,
+}
+
+impl SigAction {
+    pub const DEFAULT: SigAction = SigAction { handler: SIG_DFL, mask: 0, flags: 0 };
+}
+
+// what to do with a signal taken out of the pending mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    Ignore,
+    Terminate,
+    Stop,
+    // the mask to restore once the handler returns
+    Handler { handler: u32, saved_mask: u32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SignalState {
+    pub pending: u32,
+    pub blocked: u32,
+    actions: [SigAction; NSIG as usize],
+}
+
+impl SignalState {
+    pub const fn new() -> SignalState {
+        SignalState {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::DEFAULT; NSIG as usize],
+        }
+    }
+
+    // a forked child keeps the handlers and the mask, but nothing is pending for it
+    pub fn forked(&self) -> SignalState {
+        SignalState { pending: 0, ..*self }
+    }
+
+    // the handlers are gone with the old program, ignored signals stay ignored
+    pub fn reset_handlers(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::DEFAULT;
+            }
+        }
+    }
+
+    fn is_ignored(&self, signal: u8) -> bool {
+        match self.actions[signal as usize].handler {
+            SIG_IGN => true,
+            SIG_DFL => matches!(default_action(signal), DefaultAction::Ignore | DefaultAction::Continue),
+            _ => false,
+        }
+    }
+
+    pub fn action(&self, signal: u8) -> SigAction {
+        self.actions[signal as usize]
+    }
+
+    // gives back the previous action, the signal is discarded if it is now ignored
+    pub fn set_action(&mut self, signal: u8, action: SigAction) -> SigAction {
+        let old = core::mem::replace(&mut self.actions[signal as usize], action);
+        if self.is_ignored(signal) {
+            self.pending &= !bit(signal);
+        }
+        old
+    }
+
+    pub fn set_blocked(&mut self, mask: u32) {
+        self.blocked = mask & !UNCATCHABLE & !1;
+    }
+
+    // true if the process must be woken up to handle it
+    pub fn post(&mut self, signal: u8) -> bool {
+        if signal == SIGCONT {
+            self.pending &= !STOP_SIGNALS;
+        } else if bit(signal) & STOP_SIGNALS != 0 {
+            self.pending &= !bit(SIGCONT);
+        }
+        if self.is_ignored(signal) {
+            return false;
+        }
+        self.pending |= bit(signal);
+        self.has_deliverable()
+    }
+
+    pub fn has_deliverable(&self) -> bool {
+        self.pending & !self.blocked != 0
+    }
+
+    // the lowest unblocked pending signal, and what to do with it
+    pub fn take(&mut self) -> Option<(u8, Disposition)> {
+        let deliverable = self.pending & !self.blocked;
+        if deliverable == 0 {
+            return None;
+        }
+        let signal = deliverable.trailing_zeros() as u8;
+        self.pending &= !bit(signal);
+        let action = self.actions[signal as usize];
+        let disposition = match action.handler {
+            SIG_IGN => Disposition::Ignore,
+            SIG_DFL => match default_action(signal) {
+                DefaultAction::Terminate => Disposition::Terminate,
+                DefaultAction::Stop => Disposition::Stop,
+                DefaultAction::Ignore | DefaultAction::Continue => Disposition::Ignore,
+            },
+            handler => {
+                let saved_mask = self.blocked;
+                let mut blocked = self.blocked | action.mask;
+                if action.flags & SA_NODEFER == 0 {
+                    blocked |= bit(signal);
+                }
+                self.set_blocked(blocked);
+                if action.flags & SA_RESETHAND != 0 {
+                    self.actions[signal as usize] = SigAction::DEFAULT;
+                }
+                Disposition::Handler { handler, saved_mask }
+            }
+        };
+        Some((signal, disposition))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KillError {
+    NoSuchProcess,
+    // kernel threads don't take signals
+    NotPermitted,
+}
+
+// `signal` 0 only checks that the process exists
+pub fn kill(pid: Pid, signal: u8) -> Result<(), KillError> {
+    scheduler::with_process(pid, |process| {
+        if !process.has_user_space() {
+            return Err(KillError::NotPermitted);
+        }
+        if signal == 0 || matches!(process.state, ProcessState::Zombie | ProcessState::Exited) {
+            return Ok(());
+        }
+        if process.state == ProcessState::Stopped && (signal == SIGCONT || signal == SIGKILL) {
+            process.state = ProcessState::Ready;
+        }
+        // a process waiting in a system call gives up with EINTR
+        if process.signals.post(signal) && process.state == ProcessState::Blocked {
+            process.state = ProcessState::Ready;
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(KillError::NoSuchProcess))
+}
+
+// the program the shell is waiting for, 0 when there is none
+static FOREGROUND: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_foreground(pid: Option<Pid>) {
+    FOREGROUND.store(pid.unwrap_or(0), Ordering::Relaxed);
+}
+
+// Ctrl+C, false if there was nobody to interrupt
+pub fn interrupt_foreground() -> bool {
+    match FOREGROUND.load(Ordering::Relaxed) {
+        0 => false,
+        pid => kill(pid, SIGINT).is_ok(),
+    }
+}
+
+// what a handler finds on the user stack, lowest address first. Returning
+// from the handler runs the trampoline, which calls sigreturn.
+#[repr(C)]
+struct SignalFrame {
+    return_address: u32,
+    signal: u32,
+    saved: InterruptFrame,
+    saved_mask: u32,
+    trampoline: [u8; 8],
+}
+
+// mov eax, SYS_SIGRETURN; int 0x80
+fn trampoline() -> [u8; 8] {
+    let number = SYS_SIGRETURN.to_le_bytes();
+    [0xb8, number[0], number[1], number[2], number[3], 0xcd, 0x80, 0x90]
+}
+
+// called on every return to ring 3
+pub fn handle_signals(frame: &mut InterruptFrame) {
+    loop {
+        let taken = scheduler::with_current(|process| {
+            let taken = process.signals.take();
+            // stopped at once, a SIGCONT can come anytime after this
+            if let Some((_, Disposition::Stop)) = taken {
+                process.state = ProcessState::Stopped;
+            }
+            taken
+        });
+        let (signal, disposition) = match taken {
+            Some(taken) => taken,
+            None => return,
+        };
+        match disposition {
+            Disposition::Ignore => {}
+            Disposition::Terminate => scheduler::exit(ExitStatus::Killed(signal)),
+            // until SIGCONT or SIGKILL
+            Disposition::Stop => scheduler::schedule(),
+            Disposition::Handler { handler, saved_mask } => {
+                if !push_signal_frame(frame, signal, handler, saved_mask) {
+                    scheduler::exit(ExitStatus::Killed(SIGSEGV));
+                }
+                // the others wait for the next return to ring 3
+                return;
+            }
+        }
+    }
+}
+
+// makes `frame` return to `handler`, as if the interrupted code had called it
+fn push_signal_frame(frame: &mut InterruptFrame, signal: u8, handler: u32, saved_mask: u32) -> bool {
+    // the handler finds its argument 16 byte aligned, like after any call
+    let argument = (frame.user_esp as usize).wrapping_sub(size_of::<SignalFrame>() - 4) & !15;
+    let base = argument.wrapping_sub(4);
+    if !paging::user_accessible(base, size_of::<SignalFrame>(), true) {
+        return false;
+    }
+    let signal_frame = base as *mut SignalFrame;
+    unsafe {
+        *signal_frame = SignalFrame {
+            return_address: addr_of!((*signal_frame).trampoline) as u32,
+            signal: signal as u32,
+            saved: *frame,
+            saved_mask,
+            trampoline: trampoline(),
+        };
+    }
+    frame.eip = handler;
+    frame.user_esp = base as u32;
+    frame.eflags &= !DIRECTION_FLAG;
+    true
+}
+
+// undoes push_signal_frame once the handler returned, gives back the
+// eax of the interrupted code. None if the frame is not readable anymore.
+pub fn sigreturn(frame: &mut InterruptFrame) -> Option<u32> {
+    // the handler's ret already popped return_address
+    let base = (frame.user_esp as usize).wrapping_sub(4);
+    if !paging::user_accessible(base, size_of::<SignalFrame>(), false) {
+        return None;
+    }
+    let signal_frame = unsafe { core::ptr::read(base as *const SignalFrame) };
+    let saved = signal_frame.saved;
+    frame.edi = saved.edi;
+    frame.esi = saved.esi;
+    frame.ebp = saved.ebp;
+    frame.ebx = saved.ebx;
+    frame.edx = saved.edx;
+    frame.ecx = saved.ecx;
+    frame.eip = saved.eip;
+    frame.user_esp = saved.user_esp;
+    frame.eflags = (frame.eflags & !RESTORABLE_FLAGS) | (saved.eflags & RESTORABLE_FLAGS);
+    scheduler::with_current(|process| process.signals.set_blocked(signal_frame.saved_mask));
+    Some(saved.eax)
+}
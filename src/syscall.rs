@@ -0,0 +1,321 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::idt::{self, InterruptFrame};
+use crate::keyboard;
+use crate::paging::{self, PAGE_SIZE};
+use crate::scheduler::{self, WaitError};
+use crate::serial;
+use crate::signal::{self, KillError, SigAction, SA_NODEFER, SA_RESETHAND, SIGSEGV, SIG_BLOCK, SIG_DFL, SIG_IGN, SIG_SETMASK, SIG_UNBLOCK};
+use crate::timer;
+use crate::process::ExitStatus;
+use crate::usermode::{self, ExecError};
+use crate::vga_buffer::WRITER;
+
+// System calls go through `int 0x80`:
+//   eax          call number
+//   ebx, ecx,
+//   edx, esi,
+//   edi          arguments, in that order
+//   eax          return value, a negative value is -errno
+// every other register is preserved.
+
+pub const SYS_EXIT: u32 = 1; // exit(status) -> does not return
+pub const SYS_WRITE: u32 = 2; // write(buf, len) -> bytes written
+pub const SYS_READ_KEY: u32 = 3; // read_key() -> set 1 scancode, 0xE0xx for extended keys, waits for a key
+pub const SYS_GET_TIME: u32 = 4; // get_time() -> milliseconds since boot
+pub const SYS_SLEEP: u32 = 5; // sleep(ms) -> 0
+pub const SYS_GETPID: u32 = 6; // getpid() -> pid
+pub const SYS_FORK: u32 = 7; // fork() -> child pid in the parent, 0 in the child
+pub const SYS_WAITPID: u32 = 8; // waitpid(pid or -1 for any child, *status) -> child pid
+pub const SYS_GETPPID: u32 = 9; // getppid() -> pid of the parent
+pub const SYS_EXEC: u32 = 10; // exec(path, argv) -> does not return on success
+pub const SYS_KILL: u32 = 11; // kill(pid, signal) -> 0
+pub const SYS_SIGNAL: u32 = 12; // signal(signal, handler) -> previous handler
+pub const SYS_SIGACTION: u32 = 13; // sigaction(signal, *act, *oldact) -> 0
+pub const SYS_SIGPROCMASK: u32 = 14; // sigprocmask(how, *set, *oldset) -> 0
+pub const SYS_SIGRETURN: u32 = 15; // sigreturn() -> only called by the signal trampoline
+
+pub const EPERM: i32 = 1;
+pub const ENOENT: i32 = 2;
+pub const ESRCH: i32 = 3;
+pub const EINTR: i32 = 4;
+pub const E2BIG: i32 = 7;
+pub const ENOEXEC: i32 = 8;
+pub const ECHILD: i32 = 10;
+pub const ENOMEM: i32 = 12;
+pub const EFAULT: i32 = 14;
+pub const EINVAL: i32 = 22;
+pub const ENAMETOOLONG: i32 = 36;
+pub const ENOSYS: i32 = 38;
+
+// more than this in one write is most likely a bug in the caller
+const MAX_WRITE: usize = 4096;
+const MAX_PATH: usize = 256;
+const MAX_ARGUMENTS: usize = 64;
+
+type Syscall = fn(&mut InterruptFrame) -> Result<u32, i32>;
+
+const SYSCALL_COUNT: usize = 16;
+
+static SYSCALLS: [Option<Syscall>; SYSCALL_COUNT] = {
+    let mut table: [Option<Syscall>; SYSCALL_COUNT] = [None; SYSCALL_COUNT];
+    table[SYS_EXIT as usize] = Some(sys_exit);
+    table[SYS_WRITE as usize] = Some(sys_write);
+    table[SYS_READ_KEY as usize] = Some(sys_read_key);
+    table[SYS_GET_TIME as usize] = Some(sys_get_time);
+    table[SYS_SLEEP as usize] = Some(sys_sleep);
+    table[SYS_GETPID as usize] = Some(sys_getpid);
+    table[SYS_FORK as usize] = Some(sys_fork);
+    table[SYS_WAITPID as usize] = Some(sys_waitpid);
+    table[SYS_GETPPID as usize] = Some(sys_getppid);
+    table[SYS_EXEC as usize] = Some(sys_exec);
+    table[SYS_KILL as usize] = Some(sys_kill);
+    table[SYS_SIGNAL as usize] = Some(sys_signal);
+    table[SYS_SIGACTION as usize] = Some(sys_sigaction);
+    table[SYS_SIGPROCMASK as usize] = Some(sys_sigprocmask);
+    table[SYS_SIGRETURN as usize] = Some(sys_sigreturn);
+    table
+};
+
+pub fn syscall_handler(frame: &mut InterruptFrame) {
+    let result = match SYSCALLS.get(frame.eax as usize) {
+        Some(Some(syscall)) => syscall(frame),
+        _ => Err(ENOSYS),
+    };
+    frame.eax = match result {
+        Ok(value) => value,
+        Err(errno) => (-errno) as u32,
+    };
+}
+
+fn sys_exit(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    if !usermode::from_user_mode(frame) {
+        return Err(EPERM);
+    }
+    // only the low byte makes it to the parent, like on UNIX
+    scheduler::exit(ExitStatus::Exited(frame.ebx as u8));
+}
+
+fn sys_write(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    let (buf, len) = (frame.ebx as usize, frame.ecx as usize);
+    if len > MAX_WRITE {
+        return Err(EINVAL);
+    }
+    if !paging::user_accessible(buf, len, false) {
+        return Err(EFAULT);
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(buf as *const u8, len) };
+    // MAX_WRITE keeps this short, and nobody waits on the writer behind a
+    // preempted process
+    idt::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        for &byte in bytes {
+            writer.write_byte(byte);
+        }
+    });
+    if serial::is_mirroring() {
+        serial::write_bytes(bytes);
+    }
+    Ok(len as u32)
+}
+
+fn sys_read_key(_frame: &mut InterruptFrame) -> Result<u32, i32> {
+    keyboard::wait_key().map(|event| event.scancode() as u32).map_err(|_| EINTR)
+}
+
+fn sys_get_time(_frame: &mut InterruptFrame) -> Result<u32, i32> {
+    Ok(timer::uptime_ms() as u32)
+}
+
+fn sys_sleep(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    timer::sleep_ms(frame.ebx).map_err(|_| EINTR)?;
+    Ok(0)
+}
+
+fn sys_getpid(_frame: &mut InterruptFrame) -> Result<u32, i32> {
+    Ok(scheduler::current_pid())
+}
+
+fn sys_fork(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    // kernel threads have no user state to copy
+    if !usermode::from_user_mode(frame) {
+        return Err(EPERM);
+    }
+    scheduler::fork(frame).map_err(|_| ENOMEM)
+}
+
+fn sys_waitpid(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    let pid = match frame.ebx {
+        u32::MAX => None,
+        pid => Some(pid),
+    };
+    let status = frame.ecx as usize;
+    if status != 0 && !paging::user_accessible(status, 4, true) {
+        return Err(EFAULT);
+    }
+    let (child, exit_status) = scheduler::waitpid(pid).map_err(|err| match err {
+        WaitError::NoChild => ECHILD,
+        WaitError::Interrupted => EINTR,
+    })?;
+    if status != 0 {
+        unsafe { *(status as *mut u32) = exit_status.wait_status() };
+    }
+    Ok(child)
+}
+
+fn sys_getppid(_frame: &mut InterruptFrame) -> Result<u32, i32> {
+    Ok(scheduler::parent_pid())
+}
+
+// copies a NUL terminated string out of user memory
+fn read_user_string(addr: usize, max_len: usize, too_long: i32) -> Result<String, i32> {
+    let mut bytes = Vec::new();
+    for offset in 0..max_len {
+        let byte_addr = addr.checked_add(offset).ok_or(EFAULT)?;
+        // checking each page once is enough
+        if (offset == 0 || byte_addr % PAGE_SIZE == 0) && !paging::user_accessible(byte_addr, 1, false) {
+            return Err(EFAULT);
+        }
+        match unsafe { *(byte_addr as *const u8) } {
+            0 => return String::from_utf8(bytes).map_err(|_| EINVAL),
+            byte => bytes.push(byte),
+        }
+    }
+    Err(too_long)
+}
+
+// argv is a NULL terminated array of strings, a null argv means no arguments
+fn read_user_arguments(argv: usize) -> Result<Vec<String>, i32> {
+    let mut arguments = Vec::new();
+    if argv == 0 {
+        return Ok(arguments);
+    }
+    for index in 0..=MAX_ARGUMENTS {
+        let pointer = argv.checked_add(index * 4).ok_or(EFAULT)?;
+        if !paging::user_accessible(pointer, 4, false) {
+            return Err(EFAULT);
+        }
+        match unsafe { *(pointer as *const u32) } {
+            0 => return Ok(arguments),
+            string => arguments.push(read_user_string(string as usize, usermode::MAX_ARGUMENTS_SIZE, E2BIG)?),
+        }
+    }
+    Err(E2BIG)
+}
+
+fn sys_exec(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    if !usermode::from_user_mode(frame) {
+        return Err(EPERM);
+    }
+    let path = read_user_string(frame.ebx as usize, MAX_PATH, ENAMETOOLONG)?;
+    let arguments = read_user_arguments(frame.ecx as usize)?;
+    let argv: Vec<&str> = arguments.iter().map(|argument| argument.as_str()).collect();
+    usermode::exec(frame, &path, &argv).map_err(|err| match err {
+        ExecError::NotFound => ENOENT,
+        ExecError::InvalidExecutable(_) => ENOEXEC,
+        ExecError::ArgumentsTooLong => E2BIG,
+        ExecError::OutOfMemory => ENOMEM,
+    })?;
+    // eax of the new program
+    Ok(0)
+}
+
+fn sys_kill(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    let (pid, signal) = (frame.ebx, frame.ecx);
+    if signal != 0 && !signal::is_valid(signal) {
+        return Err(EINVAL);
+    }
+    signal::kill(pid, signal as u8).map_err(|err| match err {
+        KillError::NoSuchProcess => ESRCH,
+        KillError::NotPermitted => EPERM,
+    })?;
+    Ok(0)
+}
+
+fn check_action(signal: u32, action: &SigAction) -> Result<(), i32> {
+    if !signal::is_catchable(signal) || action.flags & !(SA_NODEFER | SA_RESETHAND) != 0 {
+        return Err(EINVAL);
+    }
+    if action.handler != SIG_DFL && action.handler != SIG_IGN && action.handler as usize >= paging::KERNEL_BASE {
+        return Err(EFAULT);
+    }
+    Ok(())
+}
+
+fn sys_signal(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    let signal = frame.ebx;
+    let action = SigAction { handler: frame.ecx, ..SigAction::DEFAULT };
+    check_action(signal, &action)?;
+    let old = scheduler::with_current(|process| process.signals.set_action(signal as u8, action));
+    Ok(old.handler)
+}
+
+fn sys_sigaction(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    let (signal, act, oldact) = (frame.ebx, frame.ecx as usize, frame.edx as usize);
+    if !signal::is_valid(signal) {
+        return Err(EINVAL);
+    }
+    let action = match act {
+        0 => None,
+        act if paging::user_accessible(act, size_of::<SigAction>(), false) => {
+            Some(unsafe { core::ptr::read_unaligned(act as *const SigAction) })
+        }
+        _ => return Err(EFAULT),
+    };
+    if let Some(ref action) = action {
+        check_action(signal, action)?;
+    }
+    if oldact != 0 && !paging::user_accessible(oldact, size_of::<SigAction>(), true) {
+        return Err(EFAULT);
+    }
+    let old = scheduler::with_current(|process| match action {
+        Some(action) => process.signals.set_action(signal as u8, action),
+        None => process.signals.action(signal as u8),
+    });
+    if oldact != 0 {
+        unsafe { core::ptr::write_unaligned(oldact as *mut SigAction, old) };
+    }
+    Ok(0)
+}
+
+fn sys_sigprocmask(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    let (how, set, oldset) = (frame.ebx, frame.ecx as usize, frame.edx as usize);
+    let mask = match set {
+        0 => None,
+        set if paging::user_accessible(set, 4, false) => Some(unsafe { *(set as *const u32) }),
+        _ => return Err(EFAULT),
+    };
+    if mask.is_some() && how != SIG_BLOCK && how != SIG_UNBLOCK && how != SIG_SETMASK {
+        return Err(EINVAL);
+    }
+    if oldset != 0 && !paging::user_accessible(oldset, 4, true) {
+        return Err(EFAULT);
+    }
+    let old = scheduler::with_current(|process| {
+        let old = process.signals.blocked;
+        match (how, mask) {
+            (_, None) => {}
+            (SIG_BLOCK, Some(mask)) => process.signals.set_blocked(old | mask),
+            (SIG_UNBLOCK, Some(mask)) => process.signals.set_blocked(old & !mask),
+            (_, Some(mask)) => process.signals.set_blocked(mask),
+        }
+        old
+    });
+    if oldset != 0 {
+        unsafe { *(oldset as *mut u32) = old };
+    }
+    Ok(0)
+}
+
+fn sys_sigreturn(frame: &mut InterruptFrame) -> Result<u32, i32> {
+    if !usermode::from_user_mode(frame) {
+        return Err(EPERM);
+    }
+    // nothing sensible to go back to
+    match signal::sigreturn(frame) {
+        Some(eax) => Ok(eax),
+        None => scheduler::exit(ExitStatus::Killed(SIGSEGV)),
+    }
+}
@@ -0,0 +1,57 @@
+use core::arch::asm;
+use core::panic::PanicInfo;
+
+use crate::io::outl;
+use serial_print;
+use serial_println;
+
+// The #[test_case] functions run in QEMU right after the memory setup, the
+// results go out on the serial line and QEMU's isa-debug-exit device turns
+// the outcome into its exit status, see the test target of the Makefile.
+
+const DEBUG_EXIT_PORT: u16 = 0xF4;
+
+// QEMU exits with (code << 1) | 1, 33 and 35
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    unsafe { outl(DEBUG_EXIT_PORT, code as u32) };
+    // only without the device, outside of `make test`
+    loop {
+        unsafe { asm!("cli", "hlt", options(nomem, nostack)) };
+    }
+}
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}... ", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+// the first failed assert ends the run
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    // the assert may have failed in the middle of a serial_print!
+    unsafe { crate::serial::force_unlock() };
+    serial_println!("[failed]");
+    serial_println!("{}", info);
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -1,56 +1,7 @@
-const CMOS_ADDRESS: u16 = 0x70;
-const CMOS_DATA: u16 = 0x71;
-
 use crate::println;
 
-////RAND
-use core::cell::Cell;
-
-pub struct SimpleRng {
-    state: Cell<u32>,
-}
-
-impl SimpleRng {
-    pub fn new(seed: u32) -> Self {
-        SimpleRng {
-            state: Cell::new(seed),
-        }
-    }
-
-    pub fn next_u32(&self) -> u32 {
-        let a: u32 = 1664525;
-        let c: u32 = 1013904223;
-
-        let state = self.state.get();
-        let next_state = state.wrapping_mul(a).wrapping_add(c);
-        self.state.set(next_state);
-
-        next_state
-    }
-}
-
-//// TIME
-
-pub fn bcd_to_binary(bcd: u8) -> u8 {
-    ((bcd & 0xf0) >> 4) * 10 + (bcd & 0x0f)
-}
-
-fn read_cmos(register: u8) -> u8 {
-    unsafe {
-        use crate::io::{inb, outb};
-        outb(CMOS_ADDRESS, register);
-        inb(CMOS_DATA)
-    }
-}
-
-fn get_rtc_time() -> (u8, u8, u8) {
-    let seconds = bcd_to_binary(read_cmos(0x00));
-    let minutes = bcd_to_binary(read_cmos(0x02));
-    let hours = bcd_to_binary(read_cmos(0x04));
-    (hours, minutes, seconds)
-}
-
-///////////////////
+use kfs_core::screen;
+use kfs_core::tetris::{self as core_tetris, name_to_index, SimpleRng, ROT_ARRAY};
 
 use crate::vga_buffer::{ColorCode, Color, WRITER};
 
@@ -59,20 +10,11 @@ fn  draw_char(row: usize, col:usize, char: u8, foreground: Color, background: Co
 }
 
 fn  draw_str(row: usize, col:usize, s: &str, foreground: Color, background: Color) {
-    for (index, c) in s.chars().enumerate() {
-        draw_char(row, col + index, c as u8, foreground, background);
-    }
-}
-
-fn  draw_digit(row:usize, col:usize, n:u8, foreground: Color, background: Color) {
-    draw_char(row, col, ('0' as u8).wrapping_add(n), foreground, background)
+    screen::draw_str(&mut *WRITER.lock(), row, col, s, foreground, background);
 }
 
 fn  draw_nbr(row:usize, col:usize, n:u32, foreground: Color, background: Color) {
-    if n >= 10 {
-        draw_nbr(row, col - 1, n/10, foreground, background);
-    }
-    draw_digit(row, col, (n%10) as u8, foreground, background);
+    screen::draw_nbr(&mut *WRITER.lock(), row, col, n, foreground, background);
 }
 
 fn  clear_window() {
@@ -84,22 +26,7 @@ fn  clear_window() {
 }
 
 fn  draw_rectangle(row_up: usize, row_down: usize, col_left:usize, col_right:usize) {
-    for row in (row_up + 1)..row_down {
-        draw_char(row, col_left, 0xB3, Color::White, Color::Black)
-    }
-    for row in (row_up + 1)..row_down {
-        draw_char(row, col_right, 0xB3, Color::White, Color::Black)
-    }
-    for col in (col_left + 1)..col_right {
-        draw_char(row_up, col, 0xC4, Color::White, Color::Black)
-    }
-    for col in (col_left + 1)..col_right {
-        draw_char(row_down, col, 0xC4, Color::White, Color::Black)
-    }
-    draw_char(row_up, col_left, 0xDA, Color::White, Color::Black);
-    draw_char(row_up, col_right, 0xBF, Color::White, Color::Black);
-    draw_char(row_down, col_left, 0xC0, Color::White, Color::Black);
-    draw_char(row_down, col_right, 0xD9, Color::White, Color::Black);
+    screen::draw_rectangle(&mut *WRITER.lock(), row_up, row_down, col_left, col_right);
 }
 
 fn draw_text() {
@@ -261,18 +188,6 @@ fn  draw_board(data: Data) {
     }
 }
 
-fn  name_to_index(c: char) -> usize {
-    match c {
-        'I' => 0,
-        'J' => 1,
-        'L' => 2,
-        'O' => 3,
-        'S' => 4,
-        'Z' => 5,
-        'T' => 6,
-        _ => 7,
-    }
-}
 fn  place_current_tetrominos(data: &mut Data) {
     let save_pos_y = data.pos.y;
 
@@ -317,16 +232,14 @@ struct Data {
     level: u32,
     score: u32,
     total_line_cleared: u32,
-    scan_code: u8,
+    key: Option<Key>,
     next: char,
     current: char,
     pos: Coord,
     rot: usize,
     color: Color,
-    tick: usize,
-    counting_ticks: usize,
-    ticks_per_seconds: usize,
-    time: (u8, u8, u8),
+    gravity_level: u32,
+    gravity_callback: Option<usize>,
 }
 
 impl Data {
@@ -339,71 +252,59 @@ impl Data {
             level: 1,
             score: 0,
             total_line_cleared: 0,
-            scan_code: 0,
+            key: None,
             next: 'I',
             current: 'O',
             pos: Coord { x: 3, y: 15 },
             rot: 0,
             color: Color::Yellow,
-            tick: 0,
-            counting_ticks: 0,
-            ticks_per_seconds: 300,
-            time: (0, 0, 0),
+            gravity_level: 0,
+            gravity_callback: None,
         }
     }
 }
 
 
 fn  check_cell(data: &mut Data) -> bool {
-    for y in 0..4 {
-        for x in 0..4 {
-            if (ROT_ARRAY[name_to_index(data.current)][data.rot][y][x] != 0) &&
-                (((data.pos.x + x as i32) < 0) || ((data.pos.x + x as i32) >= 10) ||
-                ((data.pos.y + 4 - y as i32) < 0) || ((data.pos.y + 4 - y as i32) >= 22) ||
-                data.board[(data.pos.x + x as i32) as usize][(data.pos.y + 4 - y as i32) as usize] != 0) {
-                    return false;
-            }
-        }
-    }
-    true
+    core_tetris::fits(&data.board, name_to_index(data.current), data.rot, data.pos.x, data.pos.y)
 }
 
 fn  handle_keyboard_input(data: &mut Data) {
-    match data.scan_code {
-        1 => {
+    match data.key {
+        Some(Key::Escape) => {
             data.exit = true;
         },
-        37 => { //37-165
+        Some(Key::K) => {
             data.rot = (data.rot + 1) % 4;
             if !check_cell(data) {
                 data.rot = (data.rot + 3) % 4;
             }
         },
-        36 => { //36-164
+        Some(Key::J) => {
             data.rot = (data.rot + 3) % 4;
             if !check_cell(data) {
                 data.rot = (data.rot + 1) % 4;
             }
         },
-        30 => { //30-158
+        Some(Key::A) => {
             data.pos.x = data.pos.x - 1;
             if !check_cell(data) {
                 data.pos.x = data.pos.x + 1;
             }
         },
-        31 => { //31-159
+        Some(Key::S) => {
             data.pos.y = data.pos.y - 1;
             if !check_cell(data) {
                 data.pos.y = data.pos.y + 1;
             }
         },
-        32 => { //32-160
+        Some(Key::D) => {
             data.pos.x = data.pos.x + 1;
             if !check_cell(data) {
                 data.pos.x = data.pos.x - 1;
             }
         },
-        57 | 17 => { //57-185
+        Some(Key::Space) | Some(Key::W) => {
             while check_cell(data) {
                 data.pos.y = data.pos.y - 1;
             }
@@ -411,42 +312,14 @@ fn  handle_keyboard_input(data: &mut Data) {
         },
         _ => {},
     }
-    data.scan_code = 0;
-}
-
-fn  clear_line(data: &mut Data, y_stop: usize) {
-    for y in y_stop..20 {
-        for x in 0..10 {
-            data.board[x][y] = data.board[x][y+1];
-        }
-    }
+    data.key = None;
 }
 
 fn  clear_lines(data: &mut Data) {
-    let mut n_line_cleared = 0;
-    let mut y: i32 = 0;
-    while y < 22 {
-        for x in 0..10 {
-            if data.board[x][y as usize] == 0 {
-                break;
-            }
-            if x == 9 {
-                clear_line(data, y as usize);
-                n_line_cleared += 1;
-                data.total_line_cleared += 1;
-                y -= 1;
-            }
-        }
-        y += 1;
-    }
+    let n_line_cleared = core_tetris::clear_lines(&mut data.board);
+    data.total_line_cleared += n_line_cleared;
     data.level = data.total_line_cleared / 10 + 1;
-    match n_line_cleared {
-        1 => data.score += 100 * data.level,
-        2 => data.score += 300 * data.level,
-        3 => data.score += 500 * data.level,
-        4 => data.score += 800 * data.level,
-        _ => return,
-    }
+    data.score += core_tetris::line_score(n_line_cleared, data.level);
 }
 
 fn  finish_tetraminos(data: &mut Data, rng: &SimpleRng) {
@@ -498,25 +371,27 @@ fn  init_game(data: &mut Data, rng: &SimpleRng) {
     draw_next(data.next);
 }
 
-fn  update_tick(data: &mut Data, rng: &SimpleRng) {
-    if data.time == get_rtc_time() {
-        data.counting_ticks += 1;
-    }
-    else {
-        data.ticks_per_seconds = data.counting_ticks;
-        data.counting_ticks = 0;
-        data.time = get_rtc_time();
-    }
-    data.tick += 1;
-    let speed: usize;
-    if data.level >= 15 {
-        speed = data.ticks_per_seconds - (29 * data.ticks_per_seconds as u32 / 30) as usize;
+static GRAVITY: AtomicBool = AtomicBool::new(false);
+
+fn  gravity_tick(_ticks: u32) {
+    GRAVITY.store(true, Ordering::Relaxed);
+}
+
+fn  update_gravity(data: &mut Data) {
+    if data.gravity_callback.is_some() && data.gravity_level == data.level {
+        return;
     }
-    else {
-        speed = data.ticks_per_seconds - ((data.level + 14) * data.ticks_per_seconds as u32 / 30) as usize;
+    if let Some(id) = data.gravity_callback {
+        unregister_callback(id);
     }
-    if data.tick > speed {
-        data.tick = 0;
+    GRAVITY.store(false, Ordering::Relaxed);
+    data.gravity_callback = register_callback(gravity_tick, core_tetris::fall_period_ms(data.level));
+    data.gravity_level = data.level;
+}
+
+fn  update_tick(data: &mut Data, rng: &SimpleRng) {
+    update_gravity(data);
+    if GRAVITY.swap(false, Ordering::Relaxed) {
         data.pos.y = data.pos.y - 1;
         if !check_cell(data) {
             data.pos.y = data.pos.y + 1;
@@ -526,39 +401,40 @@ fn  update_tick(data: &mut Data, rng: &SimpleRng) {
     }
 }
 
-fn  exit_tetris() {
+fn  exit_tetris(data: &mut Data) {
+    if let Some(id) = data.gravity_callback.take() {
+        unregister_callback(id);
+    }
     WRITER.lock().toggle_cmd(true);
     println!("See you soon!");       
 }
 
-use crate::io::try_read_data;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::idt::enable_interrupts_and_hlt;
+use crate::keyboard::{read_key, Key};
+use crate::timer::{register_callback, unregister_callback, SystemClock};
 
 fn read_input(data: &mut Data) {
-    data.scan_code = try_read_data();
-}
-
-fn  wait_start_of_second(data: &mut Data) {
-    let (a, b, c) = get_rtc_time();
-    while (a, b, c) == get_rtc_time() {}
-    data.time = get_rtc_time();
+    // releases don't do anything in the game
+    data.key = read_key().filter(|event| event.pressed).map(|event| event.key);
 }
 
 pub fn ft_tetris() {
     let mut data: Data = Data::new();
     clear_window();
     draw_game_ui();
-    let (_ , minutes, seconds) = get_rtc_time();
-    let rng = SimpleRng::new(minutes as u32* 100 + seconds as u32);
+    let rng = SimpleRng::new(core_tetris::seed(&SystemClock));
     init_game(&mut data, &rng);
-    wait_start_of_second(&mut data);
     loop {
         if data.exit {
-            exit_tetris();
+            exit_tetris(&mut data);
             break;
         }
+        // nothing changes until the next key press or timer tick
+        enable_interrupts_and_hlt();
         read_input(&mut data);
         if data.game_over {
-            if data.scan_code == 1 {
+            if data.key == Some(Key::Escape) {
                 data.exit = true;
             }
             continue;
@@ -572,187 +448,50 @@ pub fn ft_tetris() {
     }
 }
 
-const ROT_ARRAY: [[[[u8; 4]; 4]; 4]; 7] = [
-        [ // I
-            [
-                [0, 0, 0, 0],
-                [1, 1, 1, 1],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 1, 0],
-                [0, 0, 1, 0],
-                [0, 0, 1, 0],
-                [0, 0, 1, 0]
-            ],
-            [
-                [0, 0, 0, 0],
-                [0, 0, 0, 0],
-                [1, 1, 1, 1],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 1, 0, 0],
-                [0, 1, 0, 0],
-                [0, 1, 0, 0],
-                [0, 1, 0, 0]
-            ]
-        ],
-        [ // J
-            [
-                [2, 0, 0, 0],
-                [2, 2, 2, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 2, 2, 0],
-                [0, 2, 0, 0],
-                [0, 2, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 0, 0],
-                [2, 2, 2, 0],
-                [0, 0, 2, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 2, 0, 0],
-                [0, 2, 0, 0],
-                [2, 2, 0, 0],
-                [0, 0, 0, 0]
-            ]
-        ],
-        [ // L
-            [
-                [0, 0, 3, 0],
-                [3, 3, 3, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 3, 0, 0],
-                [0, 3, 0, 0],
-                [0, 3, 3, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 0, 0],
-                [3, 3, 3, 0],
-                [3, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [3, 3, 0, 0],
-                [0, 3, 0, 0],
-                [0, 3, 0, 0],
-                [0, 0, 0, 0]
-            ]
-        ],
-        [ // O
-            [
-                [0, 4, 4, 0],
-                [0, 4, 4, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 4, 4, 0],
-                [0, 4, 4, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 4, 4, 0],
-                [0, 4, 4, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 4, 4, 0],
-                [0, 4, 4, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ]
-        ],
-        [ // S
-            [
-                [0, 5, 5, 0],
-                [5, 5, 0, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 5, 0, 0],
-                [0, 5, 5, 0],
-                [0, 0, 5, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 0, 0],
-                [0, 5, 5, 0],
-                [5, 5, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [5, 0, 0, 0],
-                [5, 5, 0, 0],
-                [0, 5, 0, 0],
-                [0, 0, 0, 0]
-            ]
-        ],
-        [ // Z
-            [
-                [6, 6, 0, 0],
-                [0, 6, 6, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 6, 0],
-                [0, 6, 6, 0],
-                [0, 6, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 0, 0],
-                [6, 6, 0, 0],
-                [0, 6, 6, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 6, 0, 0],
-                [6, 6, 0, 0],
-                [6, 0, 0, 0],
-                [0, 0, 0, 0]
-            ]
-        ],
-        [ // T
-            [
-                [0, 7, 0, 0],
-                [7, 7, 7, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 7, 0, 0],
-                [0, 7, 7, 0],
-                [0, 7, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 0, 0, 0],
-                [7, 7, 7, 0],
-                [0, 7, 0, 0],
-                [0, 0, 0, 0]
-            ],
-            [
-                [0, 7, 0, 0],
-                [7, 7, 0, 0],
-                [0, 7, 0, 0],
-                [0, 0, 0, 0]
-            ]
-        ]
-    ];
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the board itself is kfs_core's, this is about what the game keeps count of
+
+    fn fill_row(data: &mut Data, y: usize) {
+        for x in 0..10 {
+            data.board[x][y] = 1;
+        }
+    }
+
+    #[test_case]
+    fn four_lines_at_once_score_more() {
+        let mut data = Data::new();
+        for y in 0..4 {
+            fill_row(&mut data, y);
+        }
+        clear_lines(&mut data);
+        assert_eq!(data.total_line_cleared, 4);
+        assert_eq!(data.level, 1);
+        assert_eq!(data.score, 800);
+    }
+
+    #[test_case]
+    fn every_ten_lines_is_a_level() {
+        let mut data = Data::new();
+        data.total_line_cleared = 9;
+        data.score = 50;
+        fill_row(&mut data, 0);
+        clear_lines(&mut data);
+        assert_eq!(data.total_line_cleared, 10);
+        assert_eq!(data.level, 2);
+        // scored at the new level
+        assert_eq!(data.score, 250);
+    }
+
+    #[test_case]
+    fn nothing_cleared_changes_nothing() {
+        let mut data = Data::new();
+        data.score = 300;
+        clear_lines(&mut data);
+        assert_eq!(data.total_line_cleared, 0);
+        assert_eq!(data.level, 1);
+        assert_eq!(data.score, 300);
+    }
+}
@@ -0,0 +1,96 @@
+extern crate spin;
+
+use alloc::collections::VecDeque;
+
+use self::spin::Mutex;
+
+use crate::idt::without_interrupts;
+use crate::process::{ExitStatus, Pid};
+use crate::scheduler;
+
+// kernel threads are processes that share the kernel address space
+
+pub struct JoinHandle {
+    pid: Pid,
+}
+
+impl JoinHandle {
+    // blocks until the thread returns from its entry function
+    pub fn join(self) -> Option<ExitStatus> {
+        let pid = self.pid;
+        // joined, not detached
+        core::mem::forget(self);
+        scheduler::waitpid(Some(pid)).ok().map(|(_, status)| status)
+    }
+}
+
+// like std, a thread nobody joins is detached, it doesn't stay a zombie
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        scheduler::detach(self.pid);
+    }
+}
+
+pub fn spawn(name: &'static str, entry: fn()) -> JoinHandle {
+    JoinHandle { pid: scheduler::spawn(name, entry) }
+}
+
+// gives the rest of our time slice to whoever is ready
+pub fn yield_now() {
+    scheduler::schedule();
+}
+
+// a wait given up because a signal came in, see signal.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+// threads waiting for something to happen. Whoever makes it happen calls
+// wake_all, from an interrupt handler if need be.
+pub struct WaitQueue {
+    waiting: Mutex<VecDeque<Pid>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue { waiting: Mutex::new(VecDeque::new()) }
+    }
+
+    // `condition` is checked with interrupts disabled, so an event cannot
+    // slip in between the check and going to sleep. Only user processes get
+    // signals, kernel threads are never interrupted.
+    pub fn wait_until<F: FnMut() -> bool>(&self, mut condition: F) -> Result<(), Interrupted> {
+        loop {
+            let done = without_interrupts(|| {
+                let pid = scheduler::current_pid();
+                if condition() {
+                    return Some(Ok(()));
+                }
+                if scheduler::signal_pending() {
+                    self.waiting.lock().retain(|&waiting| waiting != pid);
+                    return Some(Err(Interrupted));
+                }
+                {
+                    let mut waiting = self.waiting.lock();
+                    if !waiting.contains(&pid) {
+                        waiting.push_back(pid);
+                    }
+                }
+                scheduler::block_current();
+                None
+            });
+            if let Some(result) = done {
+                return result;
+            }
+        }
+    }
+
+    pub fn wake_all(&self) {
+        loop {
+            let pid = without_interrupts(|| self.waiting.lock().pop_front());
+            match pid {
+                Some(pid) => scheduler::wake(pid),
+                None => break,
+            }
+        }
+    }
+}
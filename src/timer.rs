@@ -0,0 +1,145 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::idt::{self, InterruptFrame};
+use crate::io::{outb, Ports};
+use crate::pic;
+use crate::scheduler;
+use crate::thread::{Interrupted, WaitQueue};
+use kfs_core::cmos;
+use kfs_core::hal::Clock;
+
+extern crate spin;
+use self::spin::Mutex;
+
+const PIT_CHANNEL0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_BASE_FREQUENCY: u32 = 1_193_182;
+const TIMER_IRQ: u8 = 0;
+const MAX_CALLBACKS: usize = 8;
+
+pub const DEFAULT_FREQUENCY: u32 = 1000;
+
+static TICKS: AtomicU32 = AtomicU32::new(0);
+// bumped each time TICKS goes around, about every 49.7 days at 1000 Hz
+static TICKS_HIGH: AtomicU32 = AtomicU32::new(0);
+static FREQUENCY: AtomicU32 = AtomicU32::new(DEFAULT_FREQUENCY);
+
+#[derive(Clone, Copy)]
+struct TimerCallback {
+    callback: fn(u32),
+    period: u32, // in ticks
+}
+
+// sleeping threads, woken up together once the earliest of them is due
+static SLEEPERS: WaitQueue = WaitQueue::new();
+// in ticks64(), which never goes around, only touched with interrupts disabled
+static NEXT_WAKEUP: Mutex<u64> = Mutex::new(u64::MAX);
+
+static CALLBACKS: Mutex<[Option<TimerCallback>; MAX_CALLBACKS]> = Mutex::new([None; MAX_CALLBACKS]);
+
+pub fn init_timer(frequency: u32) {
+    set_frequency(frequency);
+    idt::register_irq_handler(TIMER_IRQ, timer_interrupt);
+    pic::enable_irq(TIMER_IRQ);
+}
+
+// the PIT divides its 1.193182 MHz clock by a 16 bit value,
+// so anything between ~18.2 Hz and the base frequency is doable
+pub fn set_frequency(frequency: u32) {
+    let divisor = (PIT_BASE_FREQUENCY / frequency.max(1)).clamp(1, 0xFFFF);
+    FREQUENCY.store(PIT_BASE_FREQUENCY / divisor, Ordering::Relaxed);
+    idt::without_interrupts(|| unsafe {
+        outb(PIT_COMMAND, 0x36); // channel 0, lobyte/hibyte, square wave
+        outb(PIT_CHANNEL0, (divisor & 0xFF) as u8);
+        outb(PIT_CHANNEL0, (divisor >> 8) as u8);
+    });
+}
+
+pub fn frequency() -> u32 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+pub fn ticks() -> u32 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+// never goes around, unlike ticks()
+pub fn ticks64() -> u64 {
+    // the interrupt handler updates both halves
+    idt::without_interrupts(|| (TICKS_HIGH.load(Ordering::Relaxed) as u64) << 32 | ticks() as u64)
+}
+
+pub fn uptime_ms() -> u64 {
+    ticks64() * 1000 / frequency() as u64
+}
+
+pub fn ms_to_ticks(ms: u32) -> u32 {
+    ((ms as u64 * frequency() as u64).div_ceil(1000) as u32).max(1)
+}
+
+pub fn sleep_ms(ms: u32) -> Result<(), Interrupted> {
+    let deadline = ticks64() + ms_to_ticks(ms) as u64;
+    SLEEPERS.wait_until(|| {
+        if ticks64() >= deadline {
+            return true;
+        }
+        let mut next_wakeup = NEXT_WAKEUP.lock();
+        *next_wakeup = (*next_wakeup).min(deadline);
+        false
+    })
+}
+
+// the wall clock, the CMOS real time clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn time_of_day(&self) -> (u8, u8, u8) {
+        cmos::rtc_time(&mut Ports)
+    }
+}
+
+// the callback is given the current tick count every `period_ms`,
+// from the interrupt handler so it must be short and must not block
+pub fn register_callback(callback: fn(u32), period_ms: u32) -> Option<usize> {
+    let period = ms_to_ticks(period_ms);
+    idt::without_interrupts(|| {
+        let mut callbacks = CALLBACKS.lock();
+        let slot = callbacks.iter().position(|c| c.is_none())?;
+        callbacks[slot] = Some(TimerCallback { callback, period });
+        Some(slot)
+    })
+}
+
+pub fn unregister_callback(id: usize) {
+    idt::without_interrupts(|| {
+        if id < MAX_CALLBACKS {
+            CALLBACKS.lock()[id] = None;
+        }
+    });
+}
+
+fn timer_interrupt(_frame: &mut InterruptFrame) {
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+    if ticks == 0 {
+        TICKS_HIGH.fetch_add(1, Ordering::Relaxed);
+    }
+    let callbacks = *CALLBACKS.lock();
+    for timer_callback in callbacks.iter().flatten() {
+        if ticks.is_multiple_of(timer_callback.period) {
+            (timer_callback.callback)(ticks);
+        }
+    }
+    let due = {
+        let mut next_wakeup = NEXT_WAKEUP.lock();
+        let due = ticks64() >= *next_wakeup;
+        if due {
+            *next_wakeup = u64::MAX;
+        }
+        due
+    };
+    if due {
+        SLEEPERS.wake_all();
+    }
+    // last, since it may switch to another process for a while
+    scheduler::timer_tick(ticks);
+}
@@ -0,0 +1,59 @@
+; a standalone user program, linked on its own and loaded by GRUB as a
+; module, see build_kernel.sh and grub.cfg. Prints its arguments one per
+; line and exits with argc.
+
+SYS_EXIT	equ 1
+SYS_WRITE	equ 2
+
+global _start
+
+section .text
+bits 32
+
+_start:
+    mov esi, [esp] ; argc
+    lea edi, [esp + 4] ; argv
+    mov eax, SYS_WRITE
+    mov ebx, greeting
+    mov ecx, greeting_len
+    int 0x80
+.next_argument:
+    mov ebx, [edi]
+    test ebx, ebx
+    jz .done
+    mov ecx, ebx
+.length:
+    cmp byte [ecx], 0
+    je .write
+    inc ecx
+    jmp .length
+.write:
+    sub ecx, ebx
+    mov eax, SYS_WRITE
+    int 0x80
+    mov eax, SYS_WRITE
+    mov ebx, newline
+    mov ecx, 1
+    int 0x80
+    add edi, 4
+    jmp .next_argument
+.done:
+    ; the loader must have zeroed .bss
+    mov ebx, esi
+    cmp dword [counter], 0
+    je .exit
+    mov ebx, 255
+.exit:
+    mov eax, SYS_EXIT
+    int 0x80
+    jmp $
+
+section .rodata
+greeting: db "Hello from an ELF program! My arguments are:", 10
+greeting_len equ $ - greeting
+
+section .data
+newline: db 10
+
+section .bss
+counter: resd 1
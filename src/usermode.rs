@@ -0,0 +1,261 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::elf::{ElfFile, ProgramHeader, PT_LOAD};
+use crate::idt::InterruptFrame;
+use crate::multiboot;
+use crate::paging::{self, PagingError, PAGE_SIZE, USER, WRITABLE};
+use crate::pmm;
+use crate::process::{ExitStatus, Pid};
+use crate::scheduler;
+use crate::signal::{SIGFPE, SIGILL, SIGSEGV, SIGTRAP};
+
+pub const USER_CODE_SELECTOR: u16 = 0x23;
+pub const USER_DATA_SELECTOR: u16 = 0x2B;
+
+const USER_CODE_BASE: usize = 0x0040_0000;
+const USER_STACK_TOP: usize = paging::KERNEL_BASE;
+const USER_STACK_SIZE: usize = 16 * 1024;
+
+// interrupts enabled, and the reserved bit 1
+const USER_EFLAGS: u32 = 0x202;
+
+// argv, envp and their strings must fit in this much of the user stack
+pub const MAX_ARGUMENTS_SIZE: usize = 4096;
+const DEFAULT_ENVIRONMENT: &[&str] = &["TERM=vga", "HOME=/"];
+
+extern "C" {
+    static user_test_start: u8;
+    static user_test_end: u8;
+    static user_fork_test_start: u8;
+    static user_fork_test_end: u8;
+    static user_signal_test_start: u8;
+    static user_signal_test_end: u8;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecError {
+    NotFound,
+    InvalidExecutable(&'static str),
+    ArgumentsTooLong,
+    OutOfMemory,
+}
+
+impl From<PagingError> for ExecError {
+    fn from(_: PagingError) -> ExecError {
+        // user pages are only mapped where nothing is yet
+        ExecError::OutOfMemory
+    }
+}
+
+static USER_MODE_USED: AtomicBool = AtomicBool::new(false);
+
+// whether the user selectors were ever loaded, for the gdt command
+pub fn user_mode_used() -> bool {
+    USER_MODE_USED.load(Ordering::Relaxed)
+}
+
+unsafe fn embedded_program(start: &'static u8, end: &'static u8) -> &'static [u8] {
+    let start = start as *const u8;
+    let end = end as *const u8;
+    core::slice::from_raw_parts(start, end as usize - start as usize)
+}
+
+pub fn test_program() -> &'static [u8] {
+    unsafe { embedded_program(&user_test_start, &user_test_end) }
+}
+
+pub fn fork_test_program() -> &'static [u8] {
+    unsafe { embedded_program(&user_fork_test_start, &user_fork_test_end) }
+}
+
+pub fn signal_test_program() -> &'static [u8] {
+    unsafe { embedded_program(&user_signal_test_start, &user_signal_test_end) }
+}
+
+// the frame interrupt_return pops to start running at `entry` in ring 3
+pub fn user_frame(entry: u32, user_stack: u32) -> InterruptFrame {
+    let data = USER_DATA_SELECTOR as u32;
+    InterruptFrame {
+        gs: data, fs: data, es: data, ds: data,
+        edi: 0, esi: 0, ebp: 0, esp: 0, ebx: 0, edx: 0, ecx: 0, eax: 0,
+        vector: 0,
+        error_code: 0,
+        eip: entry,
+        cs: USER_CODE_SELECTOR as u32,
+        eflags: USER_EFLAGS,
+        user_esp: user_stack,
+        user_ss: data,
+    }
+}
+
+// zeroed user pages over [start, end), pages already there are kept
+fn map_user_pages(start: usize, end: usize) -> Result<(), PagingError> {
+    for page in (start & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE) {
+        if paging::translate(page).is_some() {
+            continue;
+        }
+        let frame = pmm::allocate_frame().ok_or(PagingError::OutOfMemory)?;
+        if let Err(err) = paging::map_page(page, frame, USER | WRITABLE) {
+            pmm::free_frame(frame);
+            return Err(err);
+        }
+        unsafe { core::ptr::write_bytes(page as *mut u8, 0, PAGE_SIZE) };
+    }
+    Ok(())
+}
+
+// a new address space filled by `load` while it is the current one,
+// `load` gives back the entry point and the initial user esp
+fn build_address_space<F>(load: F) -> Result<(usize, InterruptFrame), ExecError>
+where
+    F: FnOnce() -> Result<(u32, u32), ExecError>,
+{
+    let page_directory = paging::new_address_space()?;
+    match paging::with_address_space(page_directory, load) {
+        Ok((entry, user_stack)) => {
+            USER_MODE_USED.store(true, Ordering::Relaxed);
+            Ok((page_directory, user_frame(entry, user_stack)))
+        }
+        Err(err) => {
+            paging::release_user_space(page_directory);
+            pmm::free_frame(page_directory);
+            Err(err)
+        }
+    }
+}
+
+// a new process with its own address space, running a copy of `code`
+pub fn spawn_user_program(name: &'static str, code: &[u8]) -> Result<Pid, ExecError> {
+    let (page_directory, frame) = build_address_space(|| {
+        map_user_pages(USER_CODE_BASE, USER_CODE_BASE + code.len().max(1))?;
+        map_user_pages(USER_STACK_TOP - USER_STACK_SIZE, USER_STACK_TOP)?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(code.as_ptr(), USER_CODE_BASE as *mut u8, code.len());
+        }
+        Ok((USER_CODE_BASE as u32, USER_STACK_TOP as u32))
+    })?;
+    Ok(scheduler::spawn_user(name, page_directory, &frame))
+}
+
+// programs are the modules GRUB loaded, named by the first word of their
+// command line (see grub.cfg)
+pub fn find_program(path: &str) -> Option<(&'static str, &'static [u8])> {
+    let boot_info = multiboot::boot_info()?;
+    let module = boot_info.modules().find(|module| module.cmdline.split_whitespace().next() == Some(path))?;
+    let name = module.cmdline.split_whitespace().next()?;
+    let image = unsafe {
+        core::slice::from_raw_parts(paging::phys_to_virt(module.start as usize) as *const u8, (module.end - module.start) as usize)
+    };
+    Some((name, image))
+}
+
+fn segment_pages(segment: &ProgramHeader) -> core::ops::Range<usize> {
+    let start = segment.vaddr as usize & !(PAGE_SIZE - 1);
+    start..segment.vaddr as usize + segment.memsz as usize
+}
+
+// loads an executable in the user half of the current address space, which
+// must be empty, and returns its entry point and initial stack pointer
+fn load_elf(image: &[u8], argv: &[&str], envp: &[&str]) -> Result<(u32, u32), ExecError> {
+    let elf = ElfFile::parse(image).map_err(ExecError::InvalidExecutable)?;
+    let stack_bottom = USER_STACK_TOP - USER_STACK_SIZE;
+    for segment in elf.program_headers().filter(|ph| ph.typ == PT_LOAD) {
+        let start = segment.vaddr as usize;
+        // the first page stays unmapped to catch null pointers
+        if start < PAGE_SIZE || start + segment.memsz as usize > stack_bottom {
+            return Err(ExecError::InvalidExecutable("segment outside of user space"));
+        }
+        map_user_pages(start, start + segment.memsz as usize)?;
+        let data = elf.segment_data(&segment);
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), start as *mut u8, data.len());
+            // .bss
+            core::ptr::write_bytes((start + data.len()) as *mut u8, 0, (segment.memsz - segment.filesz) as usize);
+        }
+    }
+    let entry = elf.entry() as usize;
+    if !elf.program_headers().any(|ph| ph.typ == PT_LOAD && segment_pages(&ph).contains(&entry)) {
+        return Err(ExecError::InvalidExecutable("entry point outside of the program"));
+    }
+    // pages of read-only segments lose write access, unless a writable segment shares them
+    for segment in elf.program_headers().filter(|ph| ph.typ == PT_LOAD && !ph.is_writable()) {
+        for page in segment_pages(&segment).step_by(PAGE_SIZE) {
+            let shared = elf.program_headers().any(|other| {
+                let pages = segment_pages(&other);
+                other.typ == PT_LOAD && other.is_writable() && pages.start < page + PAGE_SIZE && page < pages.end
+            });
+            if !shared {
+                paging::set_page_flags(page, USER);
+            }
+        }
+    }
+    map_user_pages(stack_bottom, USER_STACK_TOP)?;
+    let user_stack = push_arguments(argv, envp)?;
+    Ok((elf.entry(), user_stack))
+}
+
+// the initial stack of the System V ABI: argc, argv[], NULL, envp[], NULL,
+// followed by the strings themselves
+fn push_arguments(argv: &[&str], envp: &[&str]) -> Result<u32, ExecError> {
+    let strings: usize = argv.iter().chain(envp.iter()).map(|s| s.len() + 1).sum();
+    let pointers = (argv.len() + envp.len() + 3) * size_of::<u32>();
+    if strings + pointers + 16 > MAX_ARGUMENTS_SIZE {
+        return Err(ExecError::ArgumentsTooLong);
+    }
+    let mut esp = USER_STACK_TOP;
+    let mut words: Vec<u32> = Vec::with_capacity(pointers / size_of::<u32>());
+    words.push(argv.len() as u32);
+    for list in [argv, envp].iter() {
+        for string in list.iter() {
+            esp -= string.len() + 1;
+            unsafe {
+                core::ptr::copy_nonoverlapping(string.as_ptr(), esp as *mut u8, string.len());
+                *((esp + string.len()) as *mut u8) = 0;
+            }
+            words.push(esp as u32);
+        }
+        words.push(0);
+    }
+    esp = (esp & !15) - words.len() * size_of::<u32>();
+    unsafe {
+        core::ptr::copy_nonoverlapping(words.as_ptr(), esp as *mut u32, words.len());
+    }
+    Ok(esp as u32)
+}
+
+// starts the program at `path` in a new process, argv[0] should be the path
+pub fn spawn_program(path: &str, argv: &[&str]) -> Result<Pid, ExecError> {
+    let (name, image) = find_program(path).ok_or(ExecError::NotFound)?;
+    let (page_directory, frame) = build_address_space(|| load_elf(image, argv, DEFAULT_ENVIRONMENT))?;
+    Ok(scheduler::spawn_user(name, page_directory, &frame))
+}
+
+// replaces the program of the current process, `frame` is where the system
+// call returns to. Nothing changes if loading the new program fails.
+pub fn exec(frame: &mut InterruptFrame, path: &str, argv: &[&str]) -> Result<(), ExecError> {
+    let (name, image) = find_program(path).ok_or(ExecError::NotFound)?;
+    let (page_directory, new_frame) = build_address_space(|| load_elf(image, argv, DEFAULT_ENVIRONMENT))?;
+    let old_directory = scheduler::replace_address_space(name, page_directory);
+    paging::release_user_space(old_directory);
+    pmm::free_frame(old_directory);
+    *frame = new_frame;
+    Ok(())
+}
+
+pub fn from_user_mode(frame: &InterruptFrame) -> bool {
+    frame.cs & 3 == 3
+}
+
+// a CPU exception in ring 3 kills the process with the matching signal,
+// it cannot be caught since the handler would fault again on return
+pub fn kill_faulting_process(frame: &InterruptFrame) -> ! {
+    let signal = match frame.vector {
+        0 | 16 | 19 => SIGFPE,
+        1 | 3 => SIGTRAP,
+        6 => SIGILL,
+        _ => SIGSEGV,
+    };
+    scheduler::exit(ExitStatus::Killed(signal));
+}
@@ -3,48 +3,9 @@ extern crate lazy_static;
 extern crate spin;
 
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-pub enum Color {
-    Black      = 0,
-    Blue       = 1,
-    Green      = 2,
-    Cyan       = 3,
-    Red        = 4,
-    Magenta    = 5,
-    Brown      = 6,
-    LightGray  = 7,
-    DarkGray   = 8,
-    LightBlue  = 9,
-    LightGreen = 10,
-    LightCyan  = 11,
-    LightRed   = 12,
-    Pink       = 13,
-    Yellow     = 14,
-    White      = 15,
-}
-
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
-const LINE_NB: usize = 200;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(transparent)]
-pub struct ColorCode(u8);
-
-impl ColorCode {
-    pub fn new(foreground: Color, background: Color) -> ColorCode {
-        ColorCode((background as u8) << 4 | (foreground as u8))
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(C)] // to guarantee field order is kept
-pub struct ScreenChar {
-    pub ascii: u8,
-    pub color: ColorCode,
-}
+use kfs_core::hal::TextScreen;
+use kfs_core::history::{Vec, LINE_NB};
+pub use kfs_core::screen::{Color, ColorCode, ScreenChar, BUFFER_HEIGHT, BUFFER_WIDTH};
 
 // since we only write to a buffer and never read from it, we need to
 // make sure these writes won't be optimized by future versions of rust
@@ -52,47 +13,6 @@ pub struct ScreenChar {
 // are absolutely necessary
 use self::volatile::Volatile; 
 
-struct Vec {
-    buffer: [[ScreenChar; BUFFER_WIDTH]; LINE_NB],
-    oldest: usize,
-    newest: usize,
-    size: usize,
-}
-
-impl Vec {
-    fn new() -> Self {
-        Vec {
-            buffer: [[ScreenChar {
-                ascii: b' ',
-                color: ColorCode((Color::Black as u8) << 4 | (Color::White as u8)),
-            }; BUFFER_WIDTH]; LINE_NB],
-            oldest: 0,
-            newest: 0,
-            size: 0,
-        }
-    }
-
-    fn push_new_line(&mut self, line: [ScreenChar; BUFFER_WIDTH]) {
-        if self.size == self.buffer.len() {
-            self.pop_oldest_line();
-        }
-        self.buffer[self.newest] = line;
-        self.newest = (self.newest + 1) % self.buffer.len();
-        self.size += 1
-    }
-
-    fn pop_oldest_line(&mut self) -> Option<[ScreenChar; BUFFER_WIDTH]> {
-        if self.size == 0 {
-            None
-        } else {
-            let line = self.buffer[self.oldest];
-            self.oldest = (self.oldest + 1) % self.buffer.len();
-            self.size -= 1;
-            Some(line)
-        }
-    }
-}
-
 #[repr(transparent)]
 struct Vgabuffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
@@ -106,10 +26,62 @@ pub struct Writer {
     scroll: [usize; 3],
     cmd: bool,
     active_tab: usize,
-    behind_cursor: ScreenChar
+    behind_cursor: ScreenChar,
+    // the mouse pointer, drawn over the screen without being part of it
+    pointer: Option<(usize, usize)>,
+    behind_pointer: ScreenChar,
 }
 
 impl Writer {
+
+    // what is on the screen, as if the mouse pointer wasn't there
+    fn cell(&self, row: usize, col: usize) -> ScreenChar {
+        if self.pointer == Some((row, col)) {
+            return self.behind_pointer;
+        }
+        self.vga_buffer.chars[row][col].read()
+    }
+
+    fn put(&mut self, row: usize, col: usize, char: ScreenChar) {
+        if self.pointer == Some((row, col)) {
+            self.behind_pointer = char;
+            self.draw_pointer();
+        } else {
+            self.vga_buffer.chars[row][col].write(char);
+        }
+    }
+
+    // the character under it, with its colors swapped. The top bit of the
+    // background would make it blink.
+    fn draw_pointer(&mut self) {
+        if let Some((row, col)) = self.pointer {
+            let ColorCode(color) = self.behind_pointer.color;
+            self.vga_buffer.chars[row][col].write(ScreenChar {
+                ascii: self.behind_pointer.ascii,
+                color: ColorCode(color.rotate_right(4) & 0x7F),
+            });
+        }
+    }
+
+    pub fn move_pointer(&mut self, row: usize, col: usize) {
+        if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH || self.pointer == Some((row, col)) {
+            return;
+        }
+        if let Some((old_row, old_col)) = self.pointer.take() {
+            self.vga_buffer.chars[old_row][old_col].write(self.behind_pointer);
+        }
+        self.behind_pointer = self.vga_buffer.chars[row][col].read();
+        self.pointer = Some((row, col));
+        self.draw_pointer();
+    }
+
+    // only the panic screen wants it gone for good
+    #[cfg(not(test))]
+    pub fn hide_pointer(&mut self) {
+        if let Some((row, col)) = self.pointer.take() {
+            self.vga_buffer.chars[row][col].write(self.behind_pointer);
+        }
+    }
     
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
@@ -119,15 +91,15 @@ impl Writer {
                     self.column_position[self.active_tab] -= 1;
                     let row = BUFFER_HEIGHT - 1;
                     let col = self.column_position[self.active_tab];
-                    if self.vga_buffer.chars[row][0].read().ascii == b'$' {
+                    if self.cell(row, 0).ascii == b'$' {
                         for i in col..(BUFFER_WIDTH - 1) {
-                            self.vga_buffer.chars[row][i].write(ScreenChar{
-                                ascii: self.vga_buffer.chars[row][i + 1].read().ascii as u8,
+                            self.put(row, i, ScreenChar{
+                                ascii: self.cell(row, i + 1).ascii as u8,
                                 color: self.color_code,
                             });
                         }
                     }
-                    self.vga_buffer.chars[row][BUFFER_WIDTH - 1].write(ScreenChar{
+                    self.put(row, BUFFER_WIDTH - 1, ScreenChar{
                         ascii: ' ' as u8,
                         color: self.color_code,
                     });
@@ -141,15 +113,15 @@ impl Writer {
                 let row = BUFFER_HEIGHT - 1;
                 let col = self.column_position[self.active_tab];
                 let color_code = self.color_code;
-                if self.vga_buffer.chars[row][0].read().ascii == b'$' {
+                if self.cell(row, 0).ascii == b'$' {
                     for i in ((col + 1)..(BUFFER_WIDTH - 1)).rev() {
-                        self.vga_buffer.chars[row][i].write(ScreenChar{
-                            ascii: self.vga_buffer.chars[row][i - 1].read().ascii as u8,
+                        self.put(row, i, ScreenChar{
+                            ascii: self.cell(row, i - 1).ascii as u8,
                             color: self.color_code,
                         });
                     }
                 }
-                self.vga_buffer.chars[row][col].write(ScreenChar{
+                self.put(row, col, ScreenChar{
                     ascii: byte,
                     color: color_code,
                 });
@@ -163,14 +135,14 @@ impl Writer {
 
     fn update_cursor(&mut self, position: usize) {
         let row = BUFFER_HEIGHT - 1;
-        self.behind_cursor = self.vga_buffer.chars[row][position].read();
+        self.behind_cursor = self.cell(row, position);
         let cursor = match self.active_tab {
             0 => {ScreenChar { ascii: self.behind_cursor.ascii, color: ColorCode((Color::LightBlue as u8) << 4 | (Color::Black as u8)), }},
             1 => {ScreenChar { ascii: self.behind_cursor.ascii, color: ColorCode((Color::Red as u8) << 4 | (Color::Black as u8)), }},
             2 => {ScreenChar { ascii: self.behind_cursor.ascii, color: ColorCode((Color::Green as u8) << 4 | (Color::Black as u8)), }},
             _ => {ScreenChar { ascii: self.behind_cursor.ascii, color: ColorCode((Color::Black as u8) << 4 | (Color::Black as u8)), }}
         };
-        self.vga_buffer.chars[row][position].write(cursor);
+        self.put(row, position, cursor);
     }
 
     pub fn move_cursor(&mut self, offset: i8) {
@@ -180,7 +152,7 @@ impl Writer {
             return;
         }
         if col < BUFFER_WIDTH {
-            self.vga_buffer.chars[row][col].write(self.behind_cursor);
+            self.put(row, col, self.behind_cursor);
         }
         if offset > 0 && col < BUFFER_WIDTH {
             self.column_position[self.active_tab] += 1;
@@ -200,7 +172,7 @@ impl Writer {
         }; BUFFER_WIDTH];
         let row = BUFFER_HEIGHT - 1;
         for col in 0..BUFFER_WIDTH {
-            let mut char = self.vga_buffer.chars[row][col].read();
+            let mut char = self.cell(row, col);
             if char.color != self.color_code && (col > 1 || ( char.ascii != b'$' && char.ascii != b'>')) {
                 char.color = self.color_code;
             }
@@ -226,19 +198,23 @@ impl Writer {
             color: self.color_code,
         };
         for col in 0..BUFFER_WIDTH {
-            self.vga_buffer.chars[row][col].write(blank);
+            self.put(row, col, blank);
         }
     }
 
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
+        for c in s.chars() {
+            match c {
                 // printable
-                0x20..=0x7e | b'\n' | b'\x08' => self.write_byte(byte),
+                '\x20'..='\x7e' | '\n' | '\x08' => self.write_byte(c as u8),
                 // tab
-                b'\t' => self.write_string("    "),
-                // unprintable -> does nothing
-                _ => {},
+                '\t' => self.write_string("    "),
+                // what code page 437 has, the rest is unprintable -> does nothing
+                _ => {
+                    if let Some(byte) = to_cp437(c) {
+                        self.write_byte(byte);
+                    }
+                },
             }
         }
     }
@@ -266,7 +242,7 @@ impl Writer {
         for row in 0..(BUFFER_HEIGHT-2) {
             self.clear_row(row);
             for col in 0..(BUFFER_WIDTH) {
-                self.vga_buffer.chars[row][col].write(self.lines[self.active_tab].buffer[(LINE_NB - (BUFFER_HEIGHT - 2) + row + self.lines[self.active_tab].newest - self.scroll[self.active_tab]) % LINE_NB][col]);
+                self.put(row, col, self.lines[self.active_tab].visible_line(BUFFER_HEIGHT - 2, row, self.scroll[self.active_tab])[col]);
             }
         }
     }
@@ -279,78 +255,78 @@ impl Writer {
             color = Color::Green;
         }
         for col in 0..(BUFFER_WIDTH) {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][col].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, col, ScreenChar {
                 ascii: 0xc4,
                 color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
             });
         }
-        self.vga_buffer.chars[BUFFER_HEIGHT - 2][68].write(ScreenChar {
+        self.put(BUFFER_HEIGHT - 2, 68, ScreenChar {
             ascii: '/' as u8,
             color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
         });
-        self.vga_buffer.chars[BUFFER_HEIGHT - 2][69].write(ScreenChar {
+        self.put(BUFFER_HEIGHT - 2, 69, ScreenChar {
             ascii: ' ' as u8,
             color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
         });
         if self.active_tab == 0 {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][70].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, 70, ScreenChar {
                 ascii: '1' as u8,
                 color: ColorCode((color as u8) << 4 | (Color::Black as u8)),
             });
         } else {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][70].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, 70, ScreenChar {
                 ascii: '1' as u8,
                 color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
             });
         }
-        self.vga_buffer.chars[BUFFER_HEIGHT - 2][71].write(ScreenChar {
+        self.put(BUFFER_HEIGHT - 2, 71, ScreenChar {
             ascii: ' ' as u8,
             color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
         });
         if self.active_tab == 1 {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][72].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, 72, ScreenChar {
                 ascii: '2' as u8,
                 color: ColorCode((color as u8) << 4 | (Color::Black as u8)),
             });
         } else {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][72].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, 72, ScreenChar {
                 ascii: '2' as u8,
                 color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
             });
         }
-        self.vga_buffer.chars[BUFFER_HEIGHT - 2][73].write(ScreenChar {
+        self.put(BUFFER_HEIGHT - 2, 73, ScreenChar {
             ascii: ' ' as u8,
             color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
         });
         if self.active_tab == 2 {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][74].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, 74, ScreenChar {
                 ascii: '3' as u8,
                 color: ColorCode((color as u8) << 4 | (Color::Black as u8)),
             });
         } else {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 2][74].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 2, 74, ScreenChar {
                 ascii: '3' as u8,
                 color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
             });
         }
-        self.vga_buffer.chars[BUFFER_HEIGHT - 2][75].write(ScreenChar {
+        self.put(BUFFER_HEIGHT - 2, 75, ScreenChar {
             ascii: ' ' as u8,
             color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
         });
-        self.vga_buffer.chars[BUFFER_HEIGHT - 2][76].write(ScreenChar {
+        self.put(BUFFER_HEIGHT - 2, 76, ScreenChar {
             ascii: '/' as u8,
             color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
         });
         if self.cmd == true {
-            self.vga_buffer.chars[BUFFER_HEIGHT - 1][0].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 1, 0, ScreenChar {
                 ascii: b'$',
                 color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
             });
-            self.vga_buffer.chars[BUFFER_HEIGHT - 1][1].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 1, 1, ScreenChar {
                 ascii: b'>',
                 color: ColorCode((Color::Black as u8) << 4 | (color as u8)),
             });
-            self.vga_buffer.chars[BUFFER_HEIGHT - 1][2].write(ScreenChar {
+            self.put(BUFFER_HEIGHT - 1, 2, ScreenChar {
                 ascii: b' ',
                 color: ColorCode((color as u8) << 4 | (Color::Black as u8)),
             });
@@ -358,7 +334,7 @@ impl Writer {
     }
 
     pub fn set_vga_buffer(&mut self, row:usize, col: usize, byte: u8, color_code: ColorCode) {
-        self.vga_buffer.chars[row][col].write(ScreenChar{
+        self.put(row, col, ScreenChar{
             ascii: byte,
             color: color_code,
         });
@@ -400,14 +376,26 @@ impl Writer {
     }
 }
 
+// for the drawing helpers of kfs_core, the tetris screen
+impl TextScreen for Writer {
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.cell(row, col)
+    }
+
+    fn write(&mut self, row: usize, col: usize, char: ScreenChar) {
+        self.put(row, col, char);
+    }
+}
+
 use self::lazy_static::lazy_static;
 use self::spin::Mutex;
+use crate::paging::phys_to_virt;
 
 lazy_static! {
     pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
         column_position: [0; 3],
         color_code: ColorCode::new(Color::Yellow, Color::Blue),
-        vga_buffer: unsafe { &mut *(0xb8000 as *mut Vgabuffer) },
+        vga_buffer: unsafe { &mut *(phys_to_virt(0xb8000) as *mut Vgabuffer) },
         lines: [Vec::new(), Vec::new(), Vec::new()],
         scroll: [0; 3],
         cmd: false,
@@ -415,7 +403,12 @@ lazy_static! {
         behind_cursor: ScreenChar{
             ascii: b' ',
             color: ColorCode::new(Color::White, Color::Black),
-        }
+        },
+        pointer: None,
+        behind_pointer: ScreenChar{
+            ascii: b' ',
+            color: ColorCode::new(Color::White, Color::Black),
+        },
     });
 }
 
@@ -444,6 +437,9 @@ macro_rules! println {
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
     WRITER.lock().write_fmt(args).unwrap();
+    if crate::serial::is_mirroring() {
+        crate::serial::_print(args);
+    }
 }
 
 const VGA_COMMAND_PORT: u16 = 0x3D4;
@@ -497,4 +493,34 @@ color   : Changes the writing color <...arg : Color>
 There might be other hidden features...");
     WRITER.lock().toggle_cmd(true);
     println!("");
-}
\ No newline at end of file
+}
+// the upper half of code page 437, the character set of the VGA text mode
+const CP437: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+// the glyph the VGA shows for this control character
+const CP437_SECTION: u8 = 0x15;
+
+pub fn to_cp437(c: char) -> Option<u8> {
+    match c {
+        '\0'..='\x7f' => Some(c as u8),
+        '§' => Some(CP437_SECTION),
+        _ => CP437.iter().position(|&other| other == c).map(|index| 0x80 + index as u8),
+    }
+}
+
+// for reading back what is on the screen
+pub fn from_cp437(byte: u8) -> char {
+    match byte {
+        CP437_SECTION => '§',
+        0x80..=0xff => CP437[byte as usize - 0x80],
+        _ => byte as char,
+    }
+}